@@ -0,0 +1,93 @@
+//! Chunk allocation throughput, in packets per second.
+//!
+//! `hashset` runs the free list `BufferPool` had before, a `HashSet` behind a
+//! mutex, as the baseline. The other cases run the `BufferPool` of a
+//! `pv::Umem::offline()`, which is the same as the one of a registered UMEM, so
+//! no privilege is needed. Dropped packets go through the lock-free return
+//! queue and are collected on the next allocation.
+//! ```sh
+//! cargo bench --bench buffer_pool
+//! ```
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+const CHUNK_SIZE: usize = 2048;
+const CHUNK_COUNT: usize = 4096;
+const BATCH_SIZE: usize = 64;
+
+/// Free list of `BufferPool` before it became a stack.
+struct HashSetPool {
+    pool: Mutex<HashSet<u64>>,
+}
+
+impl HashSetPool {
+    fn new() -> Self {
+        let pool = (0..CHUNK_COUNT).map(|i| (i * CHUNK_SIZE) as u64).collect();
+        HashSetPool {
+            pool: Mutex::new(pool),
+        }
+    }
+
+    fn alloc(&self) -> Option<u64> {
+        let mut pool = self.pool.lock().unwrap();
+        let addr = *pool.iter().next()?;
+        pool.remove(&addr);
+        Some(addr)
+    }
+
+    fn free(&self, addr: u64) {
+        self.pool.lock().unwrap().insert(addr);
+    }
+}
+
+fn alloc_free(c: &mut Criterion) {
+    let mut group = c.benchmark_group("alloc_free");
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+
+    let hashset = HashSetPool::new();
+    let mut addrs = Vec::with_capacity(BATCH_SIZE);
+    group.bench_function("hashset", |b| {
+        b.iter(|| {
+            addrs.extend((0..BATCH_SIZE).map_while(|_| hashset.alloc()));
+            for addr in addrs.drain(..) {
+                hashset.free(black_box(addr));
+            }
+        })
+    });
+
+    let config = pv::UmemConfig::default()
+        .chunk_size(CHUNK_SIZE)
+        .chunk_count(CHUNK_COUNT);
+    let umem = pv::Umem::offline(&config).expect("Failed to create UMEM");
+    let mut packets = Vec::with_capacity(BATCH_SIZE);
+
+    group.bench_function("alloc_packet", |b| {
+        b.iter(|| {
+            packets.extend((0..BATCH_SIZE).map_while(|_| umem.alloc_packet()));
+            black_box(&packets);
+            packets.clear();
+        })
+    });
+
+    group.bench_function("alloc_packets", |b| {
+        b.iter(|| {
+            let packets = umem.alloc_packets(BATCH_SIZE);
+            black_box(packets)
+        })
+    });
+
+    group.bench_function("alloc_packets_into", |b| {
+        b.iter(|| {
+            umem.alloc_packets_into(&mut packets, BATCH_SIZE);
+            black_box(&packets);
+            packets.clear();
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, alloc_free);
+criterion_main!(benches);
@@ -55,6 +55,22 @@ fn main() {
 
     assert!(status.success(), "Failed to build libxdp");
 
+    println!("cargo:rerun-if-changed=src/bpf/divert.c");
+    let target_arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap();
+    let status = process::Command::new("clang")
+        .args(["-O2", "-g", "-Wall", "-target", "bpf"])
+        .arg(format!("-I{}", xdptools_out_dir.join("headers").display()))
+        .arg(format!("-I{}", include_dir.display()))
+        .arg(format!("-I/usr/include/{}-linux-gnu", target_arch))
+        .arg("-c")
+        .arg(src_dir.join("src/bpf/divert.c"))
+        .arg("-o")
+        .arg(out_dir.join("divert.o"))
+        .status()
+        .expect("Could not execute clang for divert program");
+
+    assert!(status.success(), "Failed to build divert program");
+
     println!("cargo:include={}", headers_dir.display());
     println!("cargo:rustc-link-search={}", libxdp_dir.display());
     println!("cargo:rustc-link-lib=static=xdp");
@@ -0,0 +1,141 @@
+//! Word change processing, generic over `pv::PacketIo` so that it also runs on pcap files.
+
+use pnet::{
+    packet::ipv4::MutableIpv4Packet,
+    packet::MutablePacket,
+    packet::{
+        ethernet::{EtherTypes, MutableEthernetPacket},
+        ip::IpNextHeaderProtocols,
+        ipv4,
+        udp::{self, MutableUdpPacket},
+    },
+};
+
+pub fn forward<F: pv::PacketIo, T: pv::PacketIo>(
+    from: &mut F,
+    to: &mut T,
+    source_word: &str,
+    target_word: &str,
+) -> Option<usize> {
+    /* initialize rx_batch_size and packet metadata */
+    let rx_batch_size: usize = 64;
+    let mut packets = from.receive(rx_batch_size);
+
+    if packets.is_empty() {
+        return None;
+    }
+
+    let received = packets.len();
+    let mut change_word_packets = to.alloc_packets(received)?;
+    for (packet, change_word_packet) in packets.iter_mut().zip(change_word_packets.iter_mut()) {
+        if is_udp(packet) {
+            change_word(packet, source_word, target_word);
+        }
+
+        let packet_data = packet.get_buffer_mut().to_vec();
+        change_word_packet.replace_data(&packet_data).unwrap();
+    }
+
+    for _ in 0..3 {
+        let sent_cnt = to.send(&mut change_word_packets);
+
+        if sent_cnt > 0 {
+            return Some(sent_cnt);
+        }
+    }
+
+    None
+}
+
+// check if packet is udp or not
+fn is_udp(packet: &mut pv::Packet) -> bool {
+    let buffer = packet.get_buffer_mut();
+    let mut eth = match MutableEthernetPacket::new(buffer) {
+        Some(eth) => eth,
+        None => return false,
+    };
+
+    match eth.get_ethertype() {
+        EtherTypes::Ipv4 => {
+            let ipv4 = MutableIpv4Packet::new(eth.payload_mut()).unwrap();
+            matches!(ipv4.get_next_level_protocol(), IpNextHeaderProtocols::Udp)
+        }
+        _ => false,
+    }
+}
+
+// change all matched source_word to target_word
+fn change_word(packet: &mut pv::Packet, source_word: &str, target_word: &str) {
+    /*
+      get difference of length between original payload and changed payload
+      get original payload data also.
+      because when extract payload from udp after change udp's length field,
+      original payload could be lost.
+    */
+    let diff = get_diff(packet, source_word, target_word);
+    let original_payload_data = get_original_payload_data(packet);
+
+    if diff.is_negative() {
+        packet
+            .resize(packet.end - packet.start - diff.wrapping_abs() as usize)
+            .unwrap();
+    } else {
+        packet
+            .resize(packet.end - packet.start + diff as usize)
+            .unwrap();
+    }
+
+    let mut eth = MutableEthernetPacket::new(packet.get_buffer_mut()).unwrap();
+    let mut ipv4 = MutableIpv4Packet::new(eth.payload_mut()).unwrap();
+    let ipv4_source_addr = ipv4.get_source();
+    let ipv4_destination_addr = ipv4.get_destination();
+
+    // change ipv4 total length field
+    ipv4.set_total_length((ipv4.get_total_length() as isize + diff) as u16);
+
+    // change udp length field
+    let mut udp = MutableUdpPacket::new(ipv4.payload_mut()).unwrap();
+    udp.set_length((udp.get_length() as isize + diff) as u16);
+
+    // change source_words to target_words
+    let payload = udp.payload_mut();
+    let new_payload_data = original_payload_data.replace(source_word, target_word);
+    let new_payload = new_payload_data.as_bytes();
+    if diff.is_negative() {
+        payload.copy_from_slice(new_payload);
+    } else {
+        payload.copy_from_slice(&new_payload[0..payload.len()]);
+    }
+
+    // change udp checksum
+    udp.set_checksum(udp::ipv4_checksum(
+        &udp.to_immutable(),
+        &ipv4_source_addr,
+        &ipv4_destination_addr,
+    ));
+
+    ipv4.set_checksum(ipv4::checksum(&ipv4.to_immutable()));
+}
+
+fn get_diff(packet: &mut pv::Packet, source_word: &str, target_word: &str) -> isize {
+    let payload_data: String;
+
+    let mut eth = MutableEthernetPacket::new(packet.get_buffer_mut()).unwrap();
+    let mut ipv4 = MutableIpv4Packet::new(eth.payload_mut()).unwrap();
+    let mut udp = MutableUdpPacket::new(ipv4.payload_mut()).unwrap();
+
+    let payload = udp.payload_mut();
+    unsafe {
+        payload_data = String::from_utf8_unchecked(payload.to_vec());
+    }
+    let new_payload_data = payload_data.replace(source_word, target_word);
+    new_payload_data.len() as isize - payload_data.len() as isize
+}
+
+fn get_original_payload_data(packet: &mut pv::Packet) -> String {
+    let mut eth = MutableEthernetPacket::new(packet.get_buffer_mut()).unwrap();
+    let mut ipv4 = MutableIpv4Packet::new(eth.payload_mut()).unwrap();
+    let mut udp = MutableUdpPacket::new(ipv4.payload_mut()).unwrap();
+    let payload = udp.payload_mut();
+    unsafe { String::from_utf8_unchecked(payload.to_vec()) }
+}
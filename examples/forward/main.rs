@@ -38,27 +38,23 @@ fn main() {
         panic!("signal is forbidden");
     }
 
-    let mut nic1 = pv::Nic::new(
-        &if_name1,
-        chunk_size,
-        chunk_count,
-        filling_ring_size,
-        completion_ring_size,
-        tx_ring_size,
-        rx_ring_size,
-    )
-    .unwrap_or_else(|err| panic!("Failed to create Nic1: {}", err));
-
-    let mut nic2 = pv::Nic::new(
-        &if_name2,
-        chunk_size,
-        chunk_count,
-        filling_ring_size,
-        completion_ring_size,
-        tx_ring_size,
-        rx_ring_size,
-    )
-    .unwrap_or_else(|err| panic!("Failed to create Nic2: {}", err));
+    let umem_config = pv::UmemConfig::default()
+        .chunk_size(chunk_size)
+        .chunk_count(chunk_count)
+        .fill_ring_size(filling_ring_size)
+        .completion_ring_size(completion_ring_size);
+    let umem =
+        pv::Umem::new(&umem_config).unwrap_or_else(|err| panic!("Failed to create UMEM: {}", err));
+
+    let config = pv::NicConfig::default()
+        .tx_ring_size(tx_ring_size)
+        .rx_ring_size(rx_ring_size);
+
+    let mut nic1 = pv::Nic::new(&if_name1, &umem, &config)
+        .unwrap_or_else(|err| panic!("Failed to create Nic1: {}", err));
+
+    let mut nic2 = pv::Nic::new(&if_name2, &umem, &config)
+        .unwrap_or_else(|err| panic!("Failed to create Nic2: {}", err));
 
     while !term.load(Ordering::Relaxed) {
         let processed1 = forward(&mut nic1, &mut nic2, dump);
@@ -1,8 +1,11 @@
 #!/bin/sh
 
+# 각 veth의 queue 개수 (기본값 1, 예: QUEUES=4 ./set_veth.sh)
+QUEUES=${QUEUES:-1}
+
 # 가상 인터페이스 (veth0, veth1), (veth2, veth3)을 host에 생성
-sudo ip link add veth0 type veth peer name veth1
-sudo ip link add veth2 type veth peer name veth3
+sudo ip link add veth0 numtxqueues "$QUEUES" numrxqueues "$QUEUES" type veth peer name veth1 numtxqueues "$QUEUES" numrxqueues "$QUEUES"
+sudo ip link add veth2 numtxqueues "$QUEUES" numrxqueues "$QUEUES" type veth peer name veth3 numtxqueues "$QUEUES" numrxqueues "$QUEUES"
 
 # 임의 namespace 생성 및 lo(loopback interface) 활성
 sudo ip netns add test1
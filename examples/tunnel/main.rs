@@ -6,7 +6,11 @@ use pnet::packet::{
     tcp::{ipv4_checksum, MutableTcpPacket, TcpOptionNumbers},
     MutablePacket,
 };
-use std::{io, net::UdpSocket, thread, time::Duration};
+use std::{
+    io,
+    net::UdpSocket,
+    os::fd::{AsRawFd, RawFd},
+};
 
 fn main() {
     let cli_options = parse_cli_options();
@@ -22,16 +26,20 @@ fn main() {
     const FILLING_RING_SIZE: usize = 64;
     const COMPLETION_RING_SIZE: usize = 64;
 
-    let mut interface = pv::Nic::new(
-        interface,
-        CHUNK_SIZE,
-        CHUNK_COUNT,
-        FILLING_RING_SIZE,
-        COMPLETION_RING_SIZE,
-        TX_RING_SIZE,
-        RX_RING_SIZE,
-    )
-    .unwrap_or_else(|err| panic!("Failed to create interface: {}", err));
+    let umem_config = pv::UmemConfig::default()
+        .chunk_size(CHUNK_SIZE)
+        .chunk_count(CHUNK_COUNT)
+        .fill_ring_size(FILLING_RING_SIZE)
+        .completion_ring_size(COMPLETION_RING_SIZE);
+    let umem =
+        pv::Umem::new(&umem_config).unwrap_or_else(|err| panic!("Failed to create UMEM: {}", err));
+
+    let config = pv::NicConfig::default()
+        .tx_ring_size(TX_RING_SIZE)
+        .rx_ring_size(RX_RING_SIZE);
+
+    let mut interface = pv::Nic::new(interface, &umem, &config)
+        .unwrap_or_else(|err| panic!("Failed to create interface: {}", err));
 
     let socket = UdpSocket::bind(source).unwrap();
     socket
@@ -73,15 +81,29 @@ fn main() {
                 buf.fill(0);
             }
             Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                // wait until network socket is ready, typically implemented
-                // via platform-specific APIs such as epoll or IOCP
-                thread::sleep(Duration::from_millis(100));
+                // wait until the interface or the network socket is ready
+                wait_readable(&[interface.as_raw_fd(), socket.as_raw_fd()], 100);
             }
             Err(e) => panic!("encountered IO error: {e}"),
         }
     }
 }
 
+fn wait_readable(fds: &[RawFd], timeout_ms: i32) {
+    let mut pfds: Vec<libc::pollfd> = fds
+        .iter()
+        .map(|&fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+
+    unsafe {
+        libc::poll(pfds.as_mut_ptr(), pfds.len() as libc::nfds_t, timeout_ms);
+    }
+}
+
 fn set_mms(packet: &mut pv::Packet, mms: u16) {
     let mut eth = MutableEthernetPacket::new(packet.get_buffer_mut()).unwrap();
 
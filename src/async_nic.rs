@@ -0,0 +1,84 @@
+use crate::{Nic, Packet};
+use std::io;
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+
+/// Asynchronous `pv::Nic` for Tokio
+///
+/// Waits for the XSK to become readable or writable instead of spinning.
+/// Available with the `tokio` feature.
+/// ```ignore
+/// let mut nic = pv::AsyncNic::new(pv::Nic::new("veth1", &umem, &config)?)?;
+/// loop {
+///     let mut packets = nic.receive(64).await?;
+///     nic.send(&mut packets).await?;
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AsyncNic {
+    inner: AsyncFd<Nic>,
+}
+
+impl AsyncNic {
+    /// # Description
+    /// Register `pv::Nic` in the Tokio reactor. Must be called within a Tokio runtime.
+    /// # Arguments
+    /// `nic` - `pv::Nic` to wrap
+    /// # Returns
+    /// On success, returns `pv::AsyncNic`. \
+    /// On failure, returns the registration error.
+    pub fn new(nic: Nic) -> io::Result<AsyncNic> {
+        let inner = AsyncFd::with_interest(nic, Interest::READABLE | Interest::WRITABLE)?;
+        Ok(AsyncNic { inner })
+    }
+
+    /// Wrapped `pv::Nic`.
+    pub fn get_ref(&self) -> &Nic {
+        self.inner.get_ref()
+    }
+
+    /// Mutable wrapped `pv::Nic`.
+    pub fn get_mut(&mut self) -> &mut Nic {
+        self.inner.get_mut()
+    }
+
+    /// Deregister from the Tokio reactor and return the wrapped `pv::Nic`.
+    pub fn into_inner(self) -> Nic {
+        self.inner.into_inner()
+    }
+
+    /// # Description
+    /// Receive packets, waiting until at least one is received
+    /// # Arguments
+    /// `max` - Maximum number of packets to receive
+    /// # Returns
+    /// Received packets
+    pub async fn receive(&mut self, max: usize) -> io::Result<Vec<Packet>> {
+        loop {
+            let mut guard = self.inner.readable_mut().await?;
+            let packets = guard.get_inner_mut().receive(max);
+            if !packets.is_empty() {
+                return Ok(packets);
+            }
+            guard.clear_ready();
+        }
+    }
+
+    /// # Description
+    /// Send packets, waiting for TX ring space while none can be sent \
+    /// **\*Sent packets are removed from the vector.**
+    /// # Arguments
+    /// `packets` - Packets to send
+    /// # Returns
+    /// Number of packets sent
+    pub async fn send(&mut self, packets: &mut Vec<Packet>) -> io::Result<usize> {
+        loop {
+            let mut guard = self.inner.writable_mut().await?;
+            let sent = guard.get_inner_mut().send(packets);
+            if sent > 0 || packets.is_empty() {
+                return Ok(sent);
+            }
+            guard.clear_ready();
+        }
+    }
+}
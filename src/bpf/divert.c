@@ -0,0 +1,138 @@
+// SPDX-License-Identifier: GPL-2.0
+/*
+ * Packetvisor divert program
+ *
+ * Redirects packets matching any rule in rules_map to the XSK bound to
+ * the RX queue, and passes everything else to the kernel network stack.
+ * Rules are written by pv::Nic through the map, see src/divert.rs.
+ */
+#include <linux/bpf.h>
+#include <linux/if_ether.h>
+#include <linux/in.h>
+#include <linux/ip.h>
+#include <linux/ipv6.h>
+#include <bpf/bpf_helpers.h>
+#include <bpf/bpf_endian.h>
+
+#define MAX_RULES 16
+#define MAX_QUEUES 64
+
+#define RULE_ENABLED (1 << 0)
+#define MATCH_ETHERTYPE (1 << 1)
+#define MATCH_IP_PROTO (1 << 2)
+#define MATCH_SRC_PORT (1 << 3)
+#define MATCH_DST_PORT (1 << 4)
+
+/* Must be kept in sync with struct RawRule in src/divert.rs */
+struct rule {
+	__u32 fields;
+	__u16 ethertype;
+	__u8 ip_proto;
+	__u8 pad;
+	__u16 src_port;
+	__u16 dst_port;
+};
+
+struct packet_info {
+	__u16 ethertype;
+	__u8 ip_proto;
+	__u8 has_ports;
+	__u16 src_port;
+	__u16 dst_port;
+};
+
+struct {
+	__uint(type, BPF_MAP_TYPE_XSKMAP);
+	__uint(max_entries, MAX_QUEUES);
+	__type(key, __u32);
+	__type(value, __u32);
+} xsks_map SEC(".maps");
+
+struct {
+	__uint(type, BPF_MAP_TYPE_ARRAY);
+	__uint(max_entries, MAX_RULES);
+	__type(key, __u32);
+	__type(value, struct rule);
+} rules_map SEC(".maps");
+
+static __always_inline int parse(struct xdp_md *ctx, struct packet_info *info)
+{
+	void *data = (void *)(long)ctx->data;
+	void *data_end = (void *)(long)ctx->data_end;
+	struct ethhdr *eth = data;
+	void *l4;
+
+	if ((void *)(eth + 1) > data_end)
+		return -1;
+
+	info->ethertype = bpf_ntohs(eth->h_proto);
+
+	if (info->ethertype == ETH_P_IP) {
+		struct iphdr *ip = (void *)(eth + 1);
+
+		if ((void *)(ip + 1) > data_end)
+			return 0;
+		info->ip_proto = ip->protocol;
+		l4 = (void *)ip + ip->ihl * 4;
+	} else if (info->ethertype == ETH_P_IPV6) {
+		struct ipv6hdr *ip6 = (void *)(eth + 1);
+
+		if ((void *)(ip6 + 1) > data_end)
+			return 0;
+		info->ip_proto = ip6->nexthdr;
+		l4 = ip6 + 1;
+	} else {
+		return 0;
+	}
+
+	/* TCP and UDP both start with source and destination ports */
+	if (info->ip_proto == IPPROTO_TCP || info->ip_proto == IPPROTO_UDP) {
+		__be16 *ports = l4;
+
+		if ((void *)(ports + 2) > data_end)
+			return 0;
+		info->has_ports = 1;
+		info->src_port = bpf_ntohs(ports[0]);
+		info->dst_port = bpf_ntohs(ports[1]);
+	}
+
+	return 0;
+}
+
+static __always_inline int match(const struct rule *rule, const struct packet_info *info)
+{
+	if ((rule->fields & MATCH_ETHERTYPE) && rule->ethertype != info->ethertype)
+		return 0;
+	if ((rule->fields & MATCH_IP_PROTO) && (!info->ip_proto || rule->ip_proto != info->ip_proto))
+		return 0;
+	if ((rule->fields & MATCH_SRC_PORT) && (!info->has_ports || rule->src_port != info->src_port))
+		return 0;
+	if ((rule->fields & MATCH_DST_PORT) && (!info->has_ports || rule->dst_port != info->dst_port))
+		return 0;
+	return 1;
+}
+
+SEC("xdp")
+int pv_divert(struct xdp_md *ctx)
+{
+	struct packet_info info = {};
+	__u32 i;
+
+	if (parse(ctx, &info) < 0)
+		return XDP_PASS;
+
+#pragma clang loop unroll(full)
+	for (i = 0; i < MAX_RULES; i++) {
+		__u32 key = i;
+		struct rule *rule = bpf_map_lookup_elem(&rules_map, &key);
+
+		if (!rule || !(rule->fields & RULE_ENABLED))
+			break;
+		if (match(rule, &info))
+			return bpf_redirect_map(&xsks_map, ctx->rx_queue_index, XDP_PASS);
+	}
+
+	return XDP_PASS;
+}
+
+char _license[] SEC("license") = "GPL";
@@ -0,0 +1,812 @@
+//! Capture of `pv::Packet`s to pcap or pcapng files for Wireshark, and reading them back
+//!
+//! ```ignore
+//! let config = pv::capture::CaptureConfig::default()
+//!     .format(pv::capture::CaptureFormat::Pcapng)
+//!     .rotate_size(64 << 20)
+//!     .non_blocking(true);
+//! let mut writer = pv::capture::PcapWriter::new("rx.pcapng", &config)?;
+//! let packets = nic.receive(64);
+//! writer.write_all(&packets, &nic.interface.name)?;
+//! ```
+
+use crate::{Error, Packet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const LINKTYPE_ETHERNET: u16 = 1;
+const PCAP_MAGIC_USEC: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NSEC: u32 = 0xa1b2_3c4d;
+const PCAPNG_SHB: u32 = 0x0a0d_0d0a;
+const PCAPNG_IDB: u32 = 0x0000_0001;
+const PCAPNG_SPB: u32 = 0x0000_0003;
+const PCAPNG_EPB: u32 = 0x0000_0006;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_OPT_END: u16 = 0;
+const PCAPNG_OPT_IF_NAME: u16 = 2;
+const PCAPNG_OPT_IF_TSRESOL: u16 = 9;
+const TSRESOL_USEC: u8 = 6;
+const TSRESOL_NSEC: u8 = 9;
+const MAX_BLOCK_SIZE: usize = 16 << 20;
+
+/********************************************************************
+ *
+ * Structures
+ *
+ *******************************************************************/
+/// File format of `PcapWriter`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureFormat {
+    /// Classic pcap with nanosecond timestamps. The interface is not recorded.
+    Pcap,
+    /// pcapng. Each interface gets its own interface description block.
+    Pcapng,
+}
+
+/// Configuration of `PcapWriter`
+#[derive(Debug, Clone)]
+pub struct CaptureConfig {
+    format: CaptureFormat,
+    snaplen: usize,
+    rotate_size: Option<u64>,
+    rotate_interval: Option<Duration>,
+    non_blocking: bool,
+    queue_size: usize,
+}
+
+/// Writer appending `pv::Packet` payloads to a pcap or pcapng file
+///
+/// With rotation, the first file is the given path and the next ones get
+/// a number before the extension. (ex. `rx.pcap`, `rx-1.pcap`, `rx-2.pcap`)
+#[derive(Debug)]
+pub struct PcapWriter {
+    sink: Option<FileSink>,            // blocking mode
+    queue: Option<SyncSender<Record>>, // non-blocking mode
+    worker: Option<JoinHandle<io::Result<()>>>,
+    snaplen: usize,
+    dropped: u64,
+}
+
+/// Reader of frames from a pcap or pcapng file
+///
+/// Reads files written by `PcapWriter`, Wireshark or tcpdump, in either byte order.
+/// ```ignore
+/// let mut reader = pv::capture::PcapReader::open("rx.pcapng")?;
+/// while let Some(frame) = reader.next_frame()? {
+///     println!("{:?} {} bytes", frame.time, frame.data.len());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct PcapReader {
+    file: BufReader<File>,
+    format: CaptureFormat,
+    big_endian: bool,
+    tsresol: u8,                           // pcap timestamp resolution
+    interfaces: Vec<(Option<String>, u8)>, // pcapng (if_name, if_tsresol)
+}
+
+/// Frame read by `PcapReader`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Timestamp since the UNIX epoch. Zero if the file has none.
+    pub time: Duration,
+    /// Interface name, if the pcapng file records it.
+    pub interface: Option<String>,
+    /// Length of the frame on the wire.
+    pub orig_len: usize,
+    /// Captured bytes, up to the snaplen of the file.
+    pub data: Vec<u8>,
+}
+
+/// Frame copied out of a packet.
+#[derive(Debug)]
+struct Record {
+    time: Duration, // since UNIX_EPOCH
+    interface: Arc<str>,
+    orig_len: usize,
+    data: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct FileSink {
+    path: PathBuf,
+    format: CaptureFormat,
+    snaplen: usize,
+    rotate_size: Option<u64>,
+    rotate_interval: Option<Duration>,
+
+    file: BufWriter<File>,
+    index: usize,
+    written: u64,
+    opened: Instant,
+    interfaces: Vec<Arc<str>>, // pcapng interface ids of the current file
+}
+
+/********************************************************************
+ *
+ * Implementation
+ *
+ *******************************************************************/
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            format: CaptureFormat::Pcap,
+            snaplen: 65535,
+            rotate_size: None,
+            rotate_interval: None,
+            non_blocking: false,
+            queue_size: 4096,
+        }
+    }
+}
+
+impl CaptureConfig {
+    /// File format. Default is `CaptureFormat::Pcap`.
+    pub fn format(mut self, format: CaptureFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Maximum bytes stored per packet. Longer packets are truncated.
+    pub fn snaplen(mut self, snaplen: usize) -> Self {
+        self.snaplen = snaplen;
+        self
+    }
+
+    /// Start a new file once the current one reaches `bytes`.
+    pub fn rotate_size(mut self, bytes: u64) -> Self {
+        self.rotate_size = Some(bytes);
+        self
+    }
+
+    /// Start a new file once the current one is open for `interval`.
+    pub fn rotate_interval(mut self, interval: Duration) -> Self {
+        self.rotate_interval = Some(interval);
+        self
+    }
+
+    /// Write in a background thread and drop packets when it falls behind,
+    /// so that capture never stalls the datapath.
+    pub fn non_blocking(mut self, non_blocking: bool) -> Self {
+        self.non_blocking = non_blocking;
+        self
+    }
+
+    /// Packets queued for the background thread in non-blocking mode.
+    pub fn queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size;
+        self
+    }
+
+    /// # Description
+    /// Check the configuration before the file is created.
+    /// # Returns
+    /// On success, returns `Ok(())`. \
+    /// On failure, returns `pv::Error::InvalidConfig` describing the invalid value.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.snaplen == 0 || self.snaplen > u32::MAX as usize {
+            return Err(Error::InvalidConfig(format!(
+                "The snaplen must be between 1 and {}. (Got {})",
+                u32::MAX,
+                self.snaplen
+            )));
+        }
+
+        if self.non_blocking && self.queue_size == 0 {
+            return Err(Error::InvalidConfig(
+                "The queue size must be greater than 0 in non-blocking mode.".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl PcapWriter {
+    /// # Description
+    /// Create the capture file at `path`, replacing an existing one
+    /// # Arguments
+    /// `path` - Path of the (first) capture file \
+    /// `config` - See `CaptureConfig`
+    /// # Returns
+    /// On success, returns `PcapWriter`. \
+    /// On failure, returns `pv::Error`.
+    pub fn new<P: AsRef<Path>>(path: P, config: &CaptureConfig) -> Result<PcapWriter, Error> {
+        config.validate()?;
+
+        let sink = FileSink::new(path.as_ref(), config)?;
+        let mut writer = PcapWriter {
+            sink: None,
+            queue: None,
+            worker: None,
+            snaplen: config.snaplen,
+            dropped: 0,
+        };
+
+        if config.non_blocking {
+            let (queue, records) = mpsc::sync_channel(config.queue_size);
+            writer.queue = Some(queue);
+            writer.worker = Some(thread::spawn(move || sink.run(records)));
+        } else {
+            writer.sink = Some(sink);
+        }
+
+        Ok(writer)
+    }
+
+    /// # Description
+    /// Append the payload of `packet`, received or sent on `interface`
+    /// # Arguments
+    /// `packet` - Packet to capture \
+    /// `interface` - Name of the network interface, recorded in pcapng
+    /// # Returns
+    /// On success, returns `true` if written, or `false` if dropped in non-blocking mode. \
+    /// On failure, returns `pv::Error::Io`.
+    pub fn write(&mut self, packet: &Packet, interface: &str) -> Result<bool, Error> {
+        self.write_record(packet, &Arc::from(interface), now())
+    }
+
+    /// # Description
+    /// Append the payload of `packet` with the given timestamp instead of the current time. \
+    /// For reproducible files, such as the output of `pv::offline::OfflineNic`.
+    /// # Arguments
+    /// `packet` - Packet to capture \
+    /// `interface` - Name of the network interface, recorded in pcapng \
+    /// `time` - Timestamp since the UNIX epoch
+    /// # Returns
+    /// See `PcapWriter::write()`.
+    pub fn write_at(
+        &mut self,
+        packet: &Packet,
+        interface: &str,
+        time: Duration,
+    ) -> Result<bool, Error> {
+        self.write_record(packet, &Arc::from(interface), time)
+    }
+
+    /// # Description
+    /// Append the payloads of `packets`, received or sent on `interface`
+    /// # Returns
+    /// On success, returns the number of packets written, not dropped. \
+    /// On failure, returns `pv::Error::Io`.
+    pub fn write_all(&mut self, packets: &[Packet], interface: &str) -> Result<usize, Error> {
+        let interface = Arc::from(interface);
+        let time = now();
+        let mut written = 0;
+        for packet in packets {
+            if self.write_record(packet, &interface, time)? {
+                written += 1;
+            }
+        }
+
+        Ok(written)
+    }
+
+    /// Number of packets dropped in non-blocking mode.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// # Description
+    /// Flush buffered packets to the file. \
+    /// In non-blocking mode, the background thread flushes by itself when idle.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if let Some(sink) = &mut self.sink {
+            sink.file.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn write_record(
+        &mut self,
+        packet: &Packet,
+        interface: &Arc<str>,
+        time: Duration,
+    ) -> Result<bool, Error> {
+        let payload = packet.get_buffer();
+        let record = Record {
+            time,
+            interface: interface.clone(),
+            orig_len: payload.len(),
+            data: payload[..payload.len().min(self.snaplen)].to_vec(),
+        };
+
+        if let Some(sink) = &mut self.sink {
+            sink.write(&record)?;
+            return Ok(true);
+        }
+
+        let result = match &self.queue {
+            Some(queue) => queue.try_send(record),
+            None => return Err(Error::Io(io::ErrorKind::BrokenPipe.into())),
+        };
+        match result {
+            Ok(_) => Ok(true),
+            Err(TrySendError::Full(_)) => {
+                self.dropped += 1;
+                Ok(false)
+            }
+            // The background thread stopped on an error, which join() returns.
+            Err(TrySendError::Disconnected(_)) => Err(self
+                .join()
+                .err()
+                .unwrap_or_else(|| Error::Io(io::ErrorKind::BrokenPipe.into()))),
+        }
+    }
+
+    /// Stop the background thread and return its error, if any.
+    fn join(&mut self) -> Result<(), Error> {
+        self.queue = None;
+        match self.worker.take().map(|worker| worker.join()) {
+            Some(Ok(result)) => result.map_err(Error::Io),
+            Some(Err(_)) => Err(Error::Io(io::Error::other("capture thread panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl PcapReader {
+    /// # Description
+    /// Open a pcap or pcapng file
+    /// # Arguments
+    /// `path` - Path of the capture file
+    /// # Returns
+    /// On success, returns `PcapReader` positioned at the first frame. \
+    /// On failure, returns `pv::Error::Io`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<PcapReader, Error> {
+        let mut reader = PcapReader {
+            file: BufReader::new(File::open(path)?),
+            format: CaptureFormat::Pcap,
+            big_endian: false,
+            tsresol: TSRESOL_USEC,
+            interfaces: Vec::new(),
+        };
+
+        let mut magic = [0; 4];
+        reader.file.read_exact(&mut magic)?;
+        match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (PCAPNG_SHB, _) => {
+                reader.format = CaptureFormat::Pcapng;
+                reader.read_section_header()?;
+            }
+            (PCAP_MAGIC_USEC, _) | (PCAP_MAGIC_NSEC, _) => reader.read_pcap_header(magic)?,
+            (_, PCAP_MAGIC_USEC) | (_, PCAP_MAGIC_NSEC) => {
+                reader.big_endian = true;
+                reader.read_pcap_header(magic)?;
+            }
+            _ => return Err(invalid_data("not a pcap or pcapng file")),
+        }
+
+        Ok(reader)
+    }
+
+    /// # Description
+    /// Read the next frame
+    /// # Returns
+    /// On success, returns the frame, or `None` at the end of the file. \
+    /// On failure, returns `pv::Error::Io`.
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, Error> {
+        match self.format {
+            CaptureFormat::Pcap => self.next_pcap_frame(),
+            CaptureFormat::Pcapng => self.next_pcapng_frame(),
+        }
+    }
+
+    fn read_pcap_header(&mut self, magic: [u8; 4]) -> Result<(), Error> {
+        let mut header = [0; 20];
+        self.file.read_exact(&mut header)?;
+        if self.u32(&header[16..20]) != LINKTYPE_ETHERNET as u32 {
+            return Err(invalid_data("only Ethernet captures are supported"));
+        }
+
+        self.tsresol = match self.u32(&magic) {
+            PCAP_MAGIC_NSEC => TSRESOL_NSEC,
+            _ => TSRESOL_USEC,
+        };
+
+        Ok(())
+    }
+
+    fn next_pcap_frame(&mut self) -> Result<Option<Frame>, Error> {
+        let mut header = [0; 16];
+        if !self.read_or_eof(&mut header)? {
+            return Ok(None);
+        }
+
+        let captured = self.u32(&header[8..12]) as usize;
+        if captured > MAX_BLOCK_SIZE {
+            return Err(invalid_data("record too large"));
+        }
+        let mut data = vec![0; captured];
+        self.file.read_exact(&mut data)?;
+
+        let fraction = self.u32(&header[4..8]) as u64;
+        Ok(Some(Frame {
+            time: Duration::from_secs(self.u32(&header[0..4]) as u64)
+                + timestamp(fraction, self.tsresol),
+            interface: None,
+            orig_len: self.u32(&header[12..16]) as usize,
+            data,
+        }))
+    }
+
+    /// Read the rest of a section header block, after its type.
+    fn read_section_header(&mut self) -> Result<(), Error> {
+        let mut head = [0; 8];
+        self.file.read_exact(&mut head)?;
+        self.big_endian = match u32::from_le_bytes(head[4..8].try_into().unwrap()) {
+            PCAPNG_BYTE_ORDER_MAGIC => false,
+            _ if u32::from_be_bytes(head[4..8].try_into().unwrap()) == PCAPNG_BYTE_ORDER_MAGIC => {
+                true
+            }
+            _ => return Err(invalid_data("invalid pcapng byte order")),
+        };
+
+        // Body without the byte order magic, then the trailing length.
+        let total = self.u32(&head[0..4]) as usize;
+        if !(28..=MAX_BLOCK_SIZE).contains(&total) {
+            return Err(invalid_data("invalid pcapng block length"));
+        }
+        let mut rest = vec![0; total - 12];
+        self.file.read_exact(&mut rest)?;
+
+        self.interfaces.clear();
+        Ok(())
+    }
+
+    fn next_pcapng_frame(&mut self) -> Result<Option<Frame>, Error> {
+        loop {
+            let mut block_type = [0; 4];
+            if !self.read_or_eof(&mut block_type)? {
+                return Ok(None);
+            }
+            if u32::from_le_bytes(block_type) == PCAPNG_SHB {
+                self.read_section_header()?;
+                continue;
+            }
+
+            let mut length = [0; 4];
+            self.file.read_exact(&mut length)?;
+            let total = self.u32(&length) as usize;
+            if !(12..=MAX_BLOCK_SIZE).contains(&total) || !total.is_multiple_of(4) {
+                return Err(invalid_data("invalid pcapng block length"));
+            }
+            let mut block = vec![0; total - 8];
+            self.file.read_exact(&mut block)?;
+            let body = &block[..total - 12];
+
+            match self.u32(&block_type) {
+                PCAPNG_IDB => self.read_interface(body)?,
+                PCAPNG_EPB => return self.read_enhanced_packet(body).map(Some),
+                PCAPNG_SPB => return self.read_simple_packet(body).map(Some),
+                _ => {} // Skip other blocks.
+            }
+        }
+    }
+
+    fn read_interface(&mut self, body: &[u8]) -> Result<(), Error> {
+        if body.len() < 8 {
+            return Err(invalid_data("truncated interface description block"));
+        }
+        if self.u16(&body[0..2]) != LINKTYPE_ETHERNET {
+            return Err(invalid_data("only Ethernet captures are supported"));
+        }
+
+        let mut name = None;
+        let mut tsresol = TSRESOL_USEC;
+        let mut options = &body[8..];
+        while options.len() >= 4 {
+            let code = self.u16(&options[0..2]);
+            let len = self.u16(&options[2..4]) as usize;
+            let value = options
+                .get(4..4 + len)
+                .ok_or_else(|| invalid_data("truncated pcapng option"))?;
+            match code {
+                PCAPNG_OPT_END => break,
+                PCAPNG_OPT_IF_NAME => {
+                    name = Some(
+                        String::from_utf8_lossy(value)
+                            .trim_end_matches('\0')
+                            .to_string(),
+                    )
+                }
+                PCAPNG_OPT_IF_TSRESOL if len == 1 => tsresol = value[0],
+                _ => {}
+            }
+            options = options.get((4 + len).next_multiple_of(4)..).unwrap_or(&[]);
+        }
+
+        self.interfaces.push((name, tsresol));
+        Ok(())
+    }
+
+    fn read_enhanced_packet(&self, body: &[u8]) -> Result<Frame, Error> {
+        if body.len() < 20 {
+            return Err(invalid_data("truncated enhanced packet block"));
+        }
+        let (name, tsresol) = self
+            .interfaces
+            .get(self.u32(&body[0..4]) as usize)
+            .ok_or_else(|| invalid_data("unknown pcapng interface"))?;
+        let captured = self.u32(&body[12..16]) as usize;
+        let data = body
+            .get(20..20 + captured)
+            .ok_or_else(|| invalid_data("truncated enhanced packet block"))?;
+
+        let ticks = ((self.u32(&body[4..8]) as u64) << 32) | self.u32(&body[8..12]) as u64;
+        Ok(Frame {
+            time: timestamp(ticks, *tsresol),
+            interface: name.clone(),
+            orig_len: self.u32(&body[16..20]) as usize,
+            data: data.to_vec(),
+        })
+    }
+
+    fn read_simple_packet(&self, body: &[u8]) -> Result<Frame, Error> {
+        if body.len() < 4 {
+            return Err(invalid_data("truncated simple packet block"));
+        }
+        let orig_len = self.u32(&body[0..4]) as usize;
+        let data = &body[4..];
+
+        Ok(Frame {
+            time: Duration::ZERO,
+            interface: self.interfaces.first().and_then(|(name, _)| name.clone()),
+            orig_len,
+            data: data[..orig_len.min(data.len())].to_vec(),
+        })
+    }
+
+    /// Fill `buf`, or return `false` at a clean end of the file.
+    fn read_or_eof(&mut self, buf: &mut [u8]) -> Result<bool, Error> {
+        match self.file.read(&mut buf[..1])? {
+            0 => Ok(false),
+            _ => {
+                self.file.read_exact(&mut buf[1..])?;
+                Ok(true)
+            }
+        }
+    }
+
+    fn u16(&self, bytes: &[u8]) -> u16 {
+        let bytes = bytes.try_into().unwrap();
+        match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        }
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = bytes.try_into().unwrap();
+        match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        }
+    }
+}
+
+impl FileSink {
+    fn new(path: &Path, config: &CaptureConfig) -> Result<Self, Error> {
+        let mut sink = FileSink {
+            path: path.to_path_buf(),
+            format: config.format,
+            snaplen: config.snaplen,
+            rotate_size: config.rotate_size,
+            rotate_interval: config.rotate_interval,
+            file: BufWriter::new(File::create(path)?),
+            index: 0,
+            written: 0,
+            opened: Instant::now(),
+            interfaces: Vec::new(),
+        };
+        sink.write_header()?;
+
+        Ok(sink)
+    }
+
+    /// Write records until the writer is dropped.
+    fn run(mut self, records: Receiver<Record>) -> io::Result<()> {
+        loop {
+            let record = match records.try_recv() {
+                Ok(record) => record,
+                Err(mpsc::TryRecvError::Empty) => {
+                    self.file.flush()?;
+                    match records.recv() {
+                        Ok(record) => record,
+                        Err(_) => break,
+                    }
+                }
+                Err(mpsc::TryRecvError::Disconnected) => break,
+            };
+
+            if let Err(e) = self.write(&record) {
+                eprintln!("Failed to write capture: {}", e);
+                return Err(e);
+            }
+        }
+
+        self.file.flush()
+    }
+
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        if self.should_rotate() {
+            self.rotate()?;
+        }
+
+        match self.format {
+            CaptureFormat::Pcap => self.write_pcap_record(record),
+            CaptureFormat::Pcapng => self.write_pcapng_record(record),
+        }
+    }
+
+    fn should_rotate(&self) -> bool {
+        self.rotate_size.is_some_and(|size| self.written >= size)
+            || self
+                .rotate_interval
+                .is_some_and(|interval| self.opened.elapsed() >= interval)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.index += 1;
+
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match self.path.extension() {
+            Some(ext) => format!("{}-{}.{}", stem, self.index, ext.to_string_lossy()),
+            None => format!("{}-{}", stem, self.index),
+        };
+        self.file = BufWriter::new(File::create(self.path.with_file_name(name))?);
+        self.written = 0;
+        self.opened = Instant::now();
+        self.interfaces.clear();
+
+        self.write_header()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        match self.format {
+            CaptureFormat::Pcap => {
+                let mut header = Vec::with_capacity(24);
+                header.extend_from_slice(&PCAP_MAGIC_NSEC.to_le_bytes());
+                header.extend_from_slice(&2u16.to_le_bytes()); // version major
+                header.extend_from_slice(&4u16.to_le_bytes()); // version minor
+                header.extend_from_slice(&0i32.to_le_bytes()); // thiszone
+                header.extend_from_slice(&0u32.to_le_bytes()); // sigfigs
+                header.extend_from_slice(&(self.snaplen as u32).to_le_bytes());
+                header.extend_from_slice(&(LINKTYPE_ETHERNET as u32).to_le_bytes());
+                self.put(&header)
+            }
+            CaptureFormat::Pcapng => {
+                let mut body = Vec::with_capacity(16);
+                body.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes());
+                body.extend_from_slice(&1u16.to_le_bytes()); // version major
+                body.extend_from_slice(&0u16.to_le_bytes()); // version minor
+                body.extend_from_slice(&(-1i64).to_le_bytes()); // section length unknown
+                self.put_block(PCAPNG_SHB, &body)
+            }
+        }
+    }
+
+    fn write_pcap_record(&mut self, record: &Record) -> io::Result<()> {
+        let mut header = Vec::with_capacity(16);
+        header.extend_from_slice(&(record.time.as_secs() as u32).to_le_bytes());
+        header.extend_from_slice(&record.time.subsec_nanos().to_le_bytes());
+        header.extend_from_slice(&(record.data.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(record.orig_len as u32).to_le_bytes());
+        self.put(&header)?;
+        self.put(&record.data)
+    }
+
+    fn write_pcapng_record(&mut self, record: &Record) -> io::Result<()> {
+        let interface_id = self.interface_id(&record.interface)?;
+        let time = record.time.as_nanos() as u64;
+
+        let mut body = Vec::with_capacity(20 + record.data.len() + 3);
+        body.extend_from_slice(&interface_id.to_le_bytes());
+        body.extend_from_slice(&((time >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(time as u32).to_le_bytes());
+        body.extend_from_slice(&(record.data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(record.orig_len as u32).to_le_bytes());
+        body.extend_from_slice(&record.data);
+        pad(&mut body);
+        self.put_block(PCAPNG_EPB, &body)
+    }
+
+    /// pcapng interface id of `interface`, describing it first if it is new in the file.
+    fn interface_id(&mut self, interface: &Arc<str>) -> io::Result<u32> {
+        if let Some(id) = self.interfaces.iter().position(|name| name == interface) {
+            return Ok(id as u32);
+        }
+
+        let mut body = Vec::with_capacity(32 + interface.len());
+        body.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes()); // reserved
+        body.extend_from_slice(&(self.snaplen as u32).to_le_bytes());
+        put_option(&mut body, PCAPNG_OPT_IF_NAME, interface.as_bytes());
+        put_option(&mut body, PCAPNG_OPT_IF_TSRESOL, &[TSRESOL_NSEC]);
+        put_option(&mut body, PCAPNG_OPT_END, &[]);
+        self.put_block(PCAPNG_IDB, &body)?;
+
+        self.interfaces.push(interface.clone());
+        Ok(self.interfaces.len() as u32 - 1)
+    }
+
+    /// pcapng block: type, total length, body, total length
+    fn put_block(&mut self, block_type: u32, body: &[u8]) -> io::Result<()> {
+        let total = (body.len() + 12) as u32;
+        self.put(&block_type.to_le_bytes())?;
+        self.put(&total.to_le_bytes())?;
+        self.put(body)?;
+        self.put(&total.to_le_bytes())
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.file.write_all(bytes)?;
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/********************************************************************
+ *
+ * Drop
+ *
+ *******************************************************************/
+impl Drop for PcapWriter {
+    fn drop(&mut self) {
+        let result = self.flush().and_then(|_| self.join());
+        if let Err(e) = result {
+            eprintln!("Failed to finish capture: {}", e);
+        }
+    }
+}
+
+/********************************************************************
+ *
+ * Other functions
+ *
+ *******************************************************************/
+/// pcapng option: code, length, value padded to 32 bits
+fn put_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    pad(body);
+}
+
+fn pad(body: &mut Vec<u8>) {
+    body.resize(body.len().next_multiple_of(4), 0);
+}
+
+fn now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// `ticks` in units of `tsresol`: 10^-n seconds, or 2^-n seconds if the MSB is set
+fn timestamp(ticks: u64, tsresol: u8) -> Duration {
+    let exponent = (tsresol & 0x7f) as u32;
+    if tsresol & 0x80 != 0 {
+        let per_second = 1u128 << exponent.min(64);
+        return Duration::from_nanos((ticks as u128 * 1_000_000_000 / per_second) as u64);
+    }
+
+    match 10u64.checked_pow(exponent) {
+        Some(per_second) => Duration::new(
+            ticks / per_second,
+            ((ticks % per_second) as u128 * 1_000_000_000 / per_second as u128) as u32,
+        ),
+        None => Duration::ZERO,
+    }
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
@@ -0,0 +1,375 @@
+use crate::bindings::*;
+use crate::program::{AttachedProgram, ChainConfig, XdpProgram};
+use crate::{Error, XdpMode};
+use std::ffi::c_void;
+
+#[cfg(not(docsrs))]
+const DIVERT_PROGRAM: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/divert.o"));
+#[cfg(docsrs)]
+const DIVERT_PROGRAM: &[u8] = &[];
+
+const DIVERT_SECTION: &str = "xdp";
+const RULES_MAP: &str = "rules_map";
+
+/* Must be kept in sync with src/bpf/divert.c */
+const MAX_RULES: usize = 16;
+pub(crate) const MAX_QUEUES: u32 = 64;
+const RULE_ENABLED: u32 = 1 << 0;
+const MATCH_ETHERTYPE: u32 = 1 << 1;
+const MATCH_IP_PROTO: u32 = 1 << 2;
+const MATCH_SRC_PORT: u32 = 1 << 3;
+const MATCH_DST_PORT: u32 = 1 << 4;
+
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+/********************************************************************
+ *
+ * Structures
+ *
+ *******************************************************************/
+/// Packets to divert from the kernel network stack to `pv::Nic`
+///
+/// Matches are combined with `or()` and `and()`, and evaluated in the kernel
+/// by an XDP program shipped with Packetvisor. Packets that do not match are
+/// passed to the kernel network stack.
+/// ```ignore
+/// let config = pv::NicConfig::default()
+///     .divert(pv::Match::udp_dst_port(7).or(pv::Match::ethertype(0x88F7)));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    rules: Vec<Rule>, // any of them
+}
+
+/// Conjunction of header fields. `None` matches any value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Rule {
+    ethertype: Option<u16>,
+    ip_proto: Option<u8>,
+    src_port: Option<u16>,
+    dst_port: Option<u16>,
+}
+
+/* Must be kept in sync with struct rule in src/bpf/divert.c */
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Eq)]
+struct RawRule {
+    fields: u32,
+    ethertype: u16,
+    ip_proto: u8,
+    pad: u8,
+    src_port: u16,
+    dst_port: u16,
+}
+
+/********************************************************************
+ *
+ * Implementation
+ *
+ *******************************************************************/
+impl Match {
+    fn rule(rule: Rule) -> Match {
+        Match { rules: vec![rule] }
+    }
+
+    /// Every packet.
+    pub fn any() -> Match {
+        Match::rule(Rule::default())
+    }
+
+    /// Ethernet frames of `ethertype`. (ex. `0x88F7` for PTP)
+    pub fn ethertype(ethertype: u16) -> Match {
+        Match::rule(Rule {
+            ethertype: Some(ethertype),
+            ..Default::default()
+        })
+    }
+
+    /// IPv4 or IPv6 packets carrying `ip_proto`.
+    pub fn ip_proto(ip_proto: u8) -> Match {
+        Match::rule(Rule {
+            ip_proto: Some(ip_proto),
+            ..Default::default()
+        })
+    }
+
+    /// TCP segments with source port `port`.
+    pub fn tcp_src_port(port: u16) -> Match {
+        Match::port(IPPROTO_TCP, Some(port), None)
+    }
+
+    /// TCP segments with destination port `port`.
+    pub fn tcp_dst_port(port: u16) -> Match {
+        Match::port(IPPROTO_TCP, None, Some(port))
+    }
+
+    /// UDP datagrams with source port `port`.
+    pub fn udp_src_port(port: u16) -> Match {
+        Match::port(IPPROTO_UDP, Some(port), None)
+    }
+
+    /// UDP datagrams with destination port `port`.
+    pub fn udp_dst_port(port: u16) -> Match {
+        Match::port(IPPROTO_UDP, None, Some(port))
+    }
+
+    fn port(ip_proto: u8, src_port: Option<u16>, dst_port: Option<u16>) -> Match {
+        Match::rule(Rule {
+            ip_proto: Some(ip_proto),
+            src_port,
+            dst_port,
+            ..Default::default()
+        })
+    }
+
+    /// Packets matching `self` or `other`.
+    pub fn or(mut self, other: Match) -> Match {
+        for rule in other.rules {
+            if !self.rules.contains(&rule) {
+                self.rules.push(rule);
+            }
+        }
+        self
+    }
+
+    /// Packets matching both `self` and `other`.
+    pub fn and(self, other: Match) -> Match {
+        let mut result = Match { rules: Vec::new() };
+        for a in &self.rules {
+            for b in &other.rules {
+                if let Some(rule) = a.and(b) {
+                    result = result.or(Match::rule(rule));
+                }
+            }
+        }
+        result
+    }
+
+    /// Attach the divert program to `ifindex` and write the rules into it.
+    pub(crate) fn attach(
+        &self,
+        ifindex: u32,
+        mode: XdpMode,
+        chain: &ChainConfig,
+    ) -> Result<AttachedProgram, Error> {
+        if self.rules.len() > MAX_RULES {
+            return Err(Error::InvalidConfig(format!(
+                "Too many divert rules. (Max = {}, Got {})",
+                MAX_RULES,
+                self.rules.len()
+            )));
+        }
+
+        let program = XdpProgram::from_bytes(DIVERT_PROGRAM, DIVERT_SECTION);
+        let attached = program.attach(ifindex, mode, chain)?;
+        let map_fd = attached.map_fd(RULES_MAP)?;
+
+        for (i, rule) in self.rules.iter().enumerate() {
+            let key = i as u32;
+            let value = rule.to_raw();
+            let ret = unsafe {
+                bpf_map_update_elem(
+                    map_fd,
+                    &key as *const u32 as *const c_void,
+                    &value as *const RawRule as *const c_void,
+                    0,
+                )
+            };
+            if ret != 0 {
+                return Err(Error::XdpProgram {
+                    context: format!("Failed to write divert rule {}", i),
+                    source: std::io::Error::last_os_error(),
+                });
+            }
+        }
+
+        Ok(attached)
+    }
+}
+
+impl Rule {
+    fn and(&self, other: &Rule) -> Option<Rule> {
+        fn merge<T: PartialEq + Copy>(a: Option<T>, b: Option<T>) -> Option<Option<T>> {
+            match (a, b) {
+                (Some(a), Some(b)) if a != b => None, // never matches
+                (Some(a), _) => Some(Some(a)),
+                (None, b) => Some(b),
+            }
+        }
+
+        Some(Rule {
+            ethertype: merge(self.ethertype, other.ethertype)?,
+            ip_proto: merge(self.ip_proto, other.ip_proto)?,
+            src_port: merge(self.src_port, other.src_port)?,
+            dst_port: merge(self.dst_port, other.dst_port)?,
+        })
+    }
+
+    fn to_raw(self) -> RawRule {
+        let mut raw = RawRule {
+            fields: RULE_ENABLED,
+            ..Default::default()
+        };
+        if let Some(ethertype) = self.ethertype {
+            raw.fields |= MATCH_ETHERTYPE;
+            raw.ethertype = ethertype;
+        }
+        if let Some(ip_proto) = self.ip_proto {
+            raw.fields |= MATCH_IP_PROTO;
+            raw.ip_proto = ip_proto;
+        }
+        if let Some(src_port) = self.src_port {
+            raw.fields |= MATCH_SRC_PORT;
+            raw.src_port = src_port;
+        }
+        if let Some(dst_port) = self.dst_port {
+            raw.fields |= MATCH_DST_PORT;
+            raw.dst_port = dst_port;
+        }
+        raw
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(filter: &Match) -> Vec<RawRule> {
+        filter.rules.iter().map(|rule| rule.to_raw()).collect()
+    }
+
+    fn udp_dst_port(port: u16) -> RawRule {
+        RawRule {
+            fields: RULE_ENABLED | MATCH_IP_PROTO | MATCH_DST_PORT,
+            ip_proto: IPPROTO_UDP,
+            dst_port: port,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn single_rules() {
+        assert_eq!(
+            raw(&Match::any()),
+            [RawRule {
+                fields: RULE_ENABLED,
+                ..Default::default()
+            }]
+        );
+        assert_eq!(raw(&Match::udp_dst_port(7)), [udp_dst_port(7)]);
+        assert_eq!(
+            raw(&Match::tcp_src_port(80)),
+            [RawRule {
+                fields: RULE_ENABLED | MATCH_IP_PROTO | MATCH_SRC_PORT,
+                ip_proto: IPPROTO_TCP,
+                src_port: 80,
+                ..Default::default()
+            }]
+        );
+        assert_eq!(
+            raw(&Match::ethertype(0x88F7)),
+            [RawRule {
+                fields: RULE_ENABLED | MATCH_ETHERTYPE,
+                ethertype: 0x88F7,
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn or_keeps_order_and_drops_duplicates() {
+        let filter = Match::udp_dst_port(7)
+            .or(Match::udp_dst_port(9))
+            .or(Match::udp_dst_port(7));
+        assert_eq!(raw(&filter), [udp_dst_port(7), udp_dst_port(9)]);
+    }
+
+    #[test]
+    fn and_merges_fields() {
+        let filter = Match::ethertype(0x0800).and(Match::udp_dst_port(53));
+        assert_eq!(
+            raw(&filter),
+            [RawRule {
+                fields: RULE_ENABLED | MATCH_ETHERTYPE | MATCH_IP_PROTO | MATCH_DST_PORT,
+                ethertype: 0x0800,
+                ip_proto: IPPROTO_UDP,
+                dst_port: 53,
+                ..Default::default()
+            }]
+        );
+
+        let filter = Match::udp_src_port(1).and(Match::udp_dst_port(2));
+        assert_eq!(
+            raw(&filter),
+            [RawRule {
+                fields: RULE_ENABLED | MATCH_IP_PROTO | MATCH_SRC_PORT | MATCH_DST_PORT,
+                ip_proto: IPPROTO_UDP,
+                src_port: 1,
+                dst_port: 2,
+                ..Default::default()
+            }]
+        );
+
+        assert_eq!(
+            Match::any().and(Match::udp_dst_port(7)),
+            Match::udp_dst_port(7)
+        );
+    }
+
+    #[test]
+    fn and_drops_conflicting_rules() {
+        assert!(raw(&Match::tcp_dst_port(80).and(Match::udp_dst_port(80))).is_empty());
+        assert!(raw(&Match::udp_dst_port(7).and(Match::udp_dst_port(9))).is_empty());
+
+        let filter = Match::udp_dst_port(7)
+            .or(Match::udp_dst_port(9))
+            .and(Match::udp_dst_port(9).or(Match::ip_proto(IPPROTO_UDP)));
+        assert_eq!(
+            raw(&filter),
+            [udp_dst_port(7), udp_dst_port(9)],
+            "{:?}",
+            filter
+        );
+    }
+
+    #[test]
+    fn and_distributes_over_or() {
+        let filter = Match::udp_dst_port(53)
+            .or(Match::tcp_dst_port(53))
+            .and(Match::ethertype(0x86DD));
+        let rules = raw(&filter);
+        assert_eq!(rules.len(), 2);
+        for (rule, ip_proto) in rules.iter().zip([IPPROTO_UDP, IPPROTO_TCP]) {
+            assert_eq!(rule.fields & MATCH_ETHERTYPE, MATCH_ETHERTYPE);
+            assert_eq!(rule.ethertype, 0x86DD);
+            assert_eq!(rule.ip_proto, ip_proto);
+            assert_eq!(rule.dst_port, 53);
+        }
+    }
+
+    #[test]
+    fn rule_and_is_symmetric() {
+        let a = Rule {
+            ethertype: Some(0x0800),
+            ip_proto: Some(IPPROTO_UDP),
+            ..Default::default()
+        };
+        let b = Rule {
+            ip_proto: Some(IPPROTO_UDP),
+            dst_port: Some(7),
+            ..Default::default()
+        };
+        assert_eq!(a.and(&b), b.and(&a));
+        assert_eq!(
+            a.and(&b),
+            Some(Rule {
+                ethertype: Some(0x0800),
+                ip_proto: Some(IPPROTO_UDP),
+                src_port: None,
+                dst_port: Some(7),
+            })
+        );
+        assert_eq!(a.and(&Rule::default()), Some(a));
+    }
+}
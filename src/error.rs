@@ -0,0 +1,165 @@
+use crate::XdpMode;
+use std::fmt;
+use std::io;
+
+/********************************************************************
+ *
+ * Structures
+ *
+ *******************************************************************/
+/// Error returned by Packetvisor
+///
+/// Errors from the kernel or `libxdp` keep the errno in `source`,
+/// which can be read with `Error::errno()`.
+/// ```ignore
+/// match pv::Nic::new("veth1", &umem, &config) {
+///     Err(pv::Error::InterfaceNotFound(name)) => eprintln!("No such interface: {}", name),
+///     Err(e) if e.errno() == Some(libc::EBUSY) => eprintln!("Interface is busy: {}", e),
+///     ...
+/// }
+/// ```
+#[derive(Debug)]
+pub enum Error {
+    /// A configuration value is invalid.
+    InvalidConfig(String),
+    /// The network interface does not exist.
+    InterfaceNotFound(String),
+    /// Reading information of the network interface failed. (ex. MTU, queue count)
+    Interface {
+        interface: String,
+        source: io::Error,
+    },
+    /// Allocating the UMEM buffer or registering it to the kernel failed.
+    UmemCreate(io::Error),
+    /// Creating the XSK failed.
+    SocketCreate {
+        interface: String,
+        queue_id: u32,
+        mode: XdpMode,
+        source: io::Error,
+    },
+    /// Zero-copy bind mode was requested, but the driver does not support it.
+    ZeroCopyUnsupported { interface: String, queue_id: u32 },
+    /// Setting or reading an option of the XSK failed.
+    SocketOption {
+        name: &'static str,
+        source: io::Error,
+    },
+    /// Loading, attaching or configuring an XDP program failed.
+    XdpProgram { context: String, source: io::Error },
+    /// Every UMEM chunk is in use.
+    PoolEmpty,
+    /// The data does not fit in the packet buffer.
+    BufferTooSmall { requested: usize, available: usize },
+    /// The payload is shorter than the length to remove.
+    PayloadTooShort { requested: usize, available: usize },
+    /// Other I/O error.
+    Io(io::Error),
+}
+
+/********************************************************************
+ *
+ * Implementation
+ *
+ *******************************************************************/
+impl Error {
+    /// errno of the underlying OS error, if any.
+    pub fn errno(&self) -> Option<i32> {
+        match self {
+            Error::Interface { source, .. }
+            | Error::SocketCreate { source, .. }
+            | Error::SocketOption { source, .. }
+            | Error::XdpProgram { source, .. }
+            | Error::UmemCreate(source)
+            | Error::Io(source) => source.raw_os_error(),
+            Error::ZeroCopyUnsupported { .. } => Some(libc::EOPNOTSUPP),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn from_errno(errno: i32) -> io::Error {
+        io::Error::from_raw_os_error(errno.abs())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidConfig(msg) => write!(f, "{}", msg),
+            Error::InterfaceNotFound(name) => write!(f, "Interface {} not found.", name),
+            Error::Interface { interface, source } => {
+                write!(f, "Failed to read {}: {}", interface, source)
+            }
+            Error::UmemCreate(source) => write!(f, "Failed to create UMEM: {}", source),
+            Error::SocketCreate {
+                interface,
+                queue_id,
+                mode,
+                source,
+            } => write!(
+                f,
+                "xsk_socket__create failed on {} queue {} in {:?} mode: {}",
+                interface, queue_id, mode, source
+            ),
+            Error::ZeroCopyUnsupported {
+                interface,
+                queue_id,
+            } => write!(
+                f,
+                "Zero-copy is not supported on {} queue {}",
+                interface, queue_id
+            ),
+            Error::SocketOption { name, source } => {
+                write!(f, "Failed to access socket option {}: {}", name, source)
+            }
+            Error::XdpProgram { context, source } => {
+                write!(f, "{}: {}", context, source)?;
+                // libxdp cannot add a program next to one it does not manage.
+                match source.raw_os_error() {
+                    Some(libc::EEXIST) | Some(libc::EBUSY) => {
+                        write!(f, " (another XDP program not loaded by libxdp is attached)")
+                    }
+                    _ => Ok(()),
+                }
+            }
+            Error::PoolEmpty => write!(f, "Chunk Pool is empty"),
+            Error::BufferTooSmall {
+                requested,
+                available,
+            } => write!(
+                f,
+                "The requested size is too large. (Max = {}, Got {})",
+                available, requested
+            ),
+            Error::PayloadTooShort {
+                requested,
+                available,
+            } => write!(
+                f,
+                "The payload is too short. (Length = {}, Got {})",
+                available, requested
+            ),
+            Error::Io(source) => write!(f, "{}", source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Interface { source, .. }
+            | Error::SocketCreate { source, .. }
+            | Error::SocketOption { source, .. }
+            | Error::XdpProgram { source, .. }
+            | Error::UmemCreate(source)
+            | Error::Io(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -17,6 +17,13 @@
 //!   and receive packets, allowing applications to directly transmit and
 //!   receive packets in the application space. This helps to simplify the
 //!   data processing process and improve performance.
+//! * Packet buffers live in a `pv::Umem` created by the application and shared by
+//!   the `pv::Nic`s attached to it. Several `pv::Umem`s with different chunk
+//!   sizes can be used in one process.
+//! * The UMEM buffer can be backed by huge pages or a memfd and bound to a NUMA
+//!   node with `pv::UmemConfig`.
+//! * `pv::Nic`, `pv::Umem` and `pv::Packet` can be moved between threads, so RX,
+//!   processing and TX can run on different cores.
 //!
 //! **3. Easy Development with Rust**
 //! * `Packetvisor` is developed based on the Rust language, so you can use
@@ -26,6 +33,31 @@
 //! **4. Automatically detects XDP mode**
 //! * `Packetvisor` supports both `XDP` `Native(=DRV)` and `Generic(=SKB)` modes,
 //!   and the mode selection is automatically determined by `Packetvisor`.
+//!   A specific mode can be requested with `pv::NicConfig::xdp_mode()`, and
+//!   `pv::Nic::mode()` reports the mode actually in use.
+//!
+//! **5. Custom XDP programs**
+//! * By default, every packet on the bound queue is redirected to `Packetvisor`.
+//!   A user-supplied XDP program can be attached with `pv::NicConfig::xdp_program()`
+//!   to decide in the kernel which packets go to userspace.
+//! * For common cases, `pv::NicConfig::divert()` takes a `pv::Match` filter instead,
+//!   so that only matching traffic leaves the kernel network stack.
+//! * Programs are attached through the `libxdp` dispatcher, so they coexist with
+//!   other XDP programs on the interface. The order in the chain is set with
+//!   `pv::NicConfig::run_priority()` and `pv::NicConfig::chain_call()`.
+//!
+//! **6. Async support**
+//! * With the `tokio` feature, `pv::AsyncNic` waits for packets and TX ring space
+//!   in the Tokio reactor instead of spinning.
+//!
+//! **7. Metrics**
+//! * `pv::Nic::counters()` and `pv::Nic::xsk_stats()` tell where packets are lost.
+//! * With the `metrics` feature, `pv::Metrics` serves them with the UMEM and ring
+//!   occupancy on a Prometheus `/metrics` endpoint.
+//! * `pv::capture::PcapWriter` writes packets to pcap or pcapng files for Wireshark.
+//! * `pv::offline::OfflineNic` receives from and sends to pcap files with the same
+//!   `pv::Packet`. Processing code written against `pv::PacketIo` runs on either,
+//!   so it can be tested without root or a network interface.
 //!
 //! ## Examples
 //! Various examples for packet _echo_, _filtering_, _forwarding_, etc.
@@ -46,21 +78,59 @@ mod bindings {
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
 
+#[cfg(feature = "tokio")]
+mod async_nic;
+pub mod capture;
+mod divert;
+mod error;
+#[cfg(feature = "metrics")]
+mod metrics;
+pub mod offline;
+mod program;
+
+#[cfg(feature = "tokio")]
+pub use async_nic::AsyncNic;
+pub use divert::Match;
+pub use error::Error;
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+pub use program::{XdpAction, XdpProgram};
+
 use bindings::*;
 use pnet::datalink::{interfaces, NetworkInterface};
-use std::alloc::{alloc_zeroed, Layout};
-use std::cell::RefCell;
-use std::collections::hash_set::HashSet;
 use std::convert::TryInto;
-use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::ffi::{c_char, c_int, c_void, CString};
+use std::mem::ManuallyDrop;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
 use std::ptr::copy;
-use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
 use std::time::Duration;
 
-use libc::strerror;
+use crossbeam_queue::ArrayQueue;
+use program::{AttachedProgram, ChainConfig};
 
 const DEFAULT_HEADROOM: usize = 256;
+const ETH_HEADER_SIZE: usize = 14;
+const MIN_CHUNK_SIZE: usize = 2048;
+const MAX_NUMA_NODES: usize = 1024;
+const TX_DRAIN_RETRIES: usize = 100;
+
+/* NUMA memory policy, see linux/mempolicy.h */
+const MPOL_BIND: c_int = 2;
+
+/* ethtool ioctl, see linux/sockios.h and linux/ethtool.h */
+const SIOCETHTOOL: libc::c_ulong = 0x8946;
+const ETHTOOL_GCHANNELS: u32 = 0x0000003c;
+
+/* AF_XDP socket level, see linux/socket.h */
+const SOL_XDP: c_int = 283;
+
+/* Busy polling socket options, see asm-generic/socket.h */
+const SO_BUSY_POLL: c_int = 46;
+const SO_PREFER_BUSY_POLL: c_int = 69;
+const SO_BUSY_POLL_BUDGET: c_int = 70;
 
 /********************************************************************
  *
@@ -70,46 +140,248 @@ const DEFAULT_HEADROOM: usize = 256;
 #[derive(Debug)]
 struct BufferPool {
     chunk_size: usize,
-    #[allow(dead_code)]
     chunk_count: usize,
 
-    pool: HashSet<u64>,
+    pool: Mutex<Vec<u64>>, // stack of free chunks, the most recently freed on top.
+    returned: ArrayQueue<u64>, // chunks freed by packets on any thread.
+    fill_owner: Vec<AtomicU32>, // id of the FQ holding each chunk, 0 if none.
+    fq_ids: AtomicU32,
 
     buffer: *mut c_void, // buffer address.
+    buffer_size: usize,  // mapped length, rounded up to the page size.
 
-    fq_size: usize,
     cq_size: usize,
 }
 
 #[derive(Debug)]
 struct Pool {
-    chunk_size: usize,
+    config: UmemConfig,
+    page_size: PageSize,
+    offline: bool, // not registered to the kernel.
 
     umem: *mut xsk_umem,
-    buffer_pool: Rc<RefCell<BufferPool>>,
+    buffer_pool: Arc<BufferPool>,
 
-    umem_fq: xsk_ring_prod,
-    umem_cq: xsk_ring_cons,
+    // Boxed, since libxdp keeps pointers to the rings.
+    umem_fq: Box<xsk_ring_prod>,
+    umem_cq: Box<xsk_ring_cons>,
 
     refcount: usize,
 }
 
+/// Configuration of `pv::Umem`
+///
+/// Every value has a default, so only the ones that differ need to be set.
+/// ```ignore
+/// let umem = pv::Umem::new(&pv::UmemConfig::default().chunk_count(4096))?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct UmemConfig {
+    chunk_size: usize,
+    chunk_count: usize,
+    fq_size: usize,
+    cq_size: usize,
+    page_size: PageSize,
+    hugepage_fallback: bool,
+    memfd: bool,
+    numa_node: Option<usize>,
+}
+
+/// Packet buffer area registered to the kernel and shared by `pv::Nic`s
+///
+/// Each `pv::Umem` has its own geometry, so several of them can be used in one process.
+/// Cloning returns another handle to the same UMEM, which can be moved to other threads.
+/// ```ignore
+/// let control = pv::Umem::new(&pv::UmemConfig::default().chunk_size(2048))?;
+/// let data = pv::Umem::new(&pv::UmemConfig::default().chunk_size(4096))?;
+/// let nic1 = pv::Nic::new("veth1", &control, &pv::NicConfig::default())?;
+/// let nic2 = pv::Nic::new("veth2", &data, &pv::NicConfig::default())?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Umem {
+    pool: Arc<Mutex<Pool>>,
+    buffer_pool: Arc<BufferPool>,
+}
+
+/// Configuration used to attach `pv::Nic` to a network interface
+///
+/// Every value has a default, so only the ones that differ need to be set.
+/// ```ignore
+/// let config = pv::NicConfig::default()
+///     .queue_id(1)
+///     .rx_ring_size(256);
+/// let nic = pv::Nic::new("veth1", &umem, &config)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct NicConfig {
+    tx_size: usize,
+    rx_size: usize,
+    queue_id: u32,
+    xdp_mode: XdpMode,
+    bind_mode: BindMode,
+    xdp_program: Option<XdpProgram>,
+    divert: Option<Match>,
+    chain: ChainConfig,
+    busy_poll: Option<BusyPoll>,
+    fill_watermark: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BusyPoll {
+    timeout_us: u32,
+    budget: u32,
+}
+
+/// XDP attach mode of `pv::Nic`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XdpMode {
+    /// Try `Native` first and fall back to `Generic` on failure.
+    /// Only used in `pv::NicConfig`; `Nic::mode()` never returns it.
+    Auto,
+    /// Native(=DRV) mode. The XDP program runs in the driver.
+    Native,
+    /// Generic(=SKB) mode. The XDP program runs after the SKB is allocated.
+    Generic,
+}
+
+/// XSK bind mode of `pv::Nic`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindMode {
+    /// Let the kernel choose. Zero-copy is used if the driver supports it.
+    /// Only used in `pv::NicConfig`; `Nic::bind_mode()` never returns it.
+    Auto,
+    /// Packets are DMA-ed directly into UMEM. Requires `XdpMode::Native`.
+    ZeroCopy,
+    /// Packets are copied between kernel buffers and UMEM.
+    Copy,
+}
+
+/// Page size backing the UMEM buffer of `pv::Umem`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSize {
+    /// Regular pages of the system page size.
+    Default,
+    /// 2 MiB huge pages. Requires reserved hugepages. (ex. `vm.nr_hugepages`)
+    Huge2M,
+    /// 1 GiB huge pages. Requires reserved 1 GiB hugepages.
+    Huge1G,
+}
+
 /// NIC Structure that supports Packetvisor
 #[derive(Debug)]
 pub struct Nic {
     /// Attached network interface information.
     /// (ex. `interface name`, `L2-3 address`, etc.)
     pub interface: NetworkInterface,
+    queue_id: u32,
+    mode: XdpMode,
+    bind_mode: BindMode,
+    program: Option<Arc<AttachedProgram>>, // shared by the Nics of a NicGroup.
+    busy_poll: bool,
+    tx_inflight: usize, // sent, but not yet back on the completion ring.
+    fill_watermark: usize,
+    fq_id: u32, // tells the chunks held by this FQ in the BufferPool.
+    backpressure: bool,
+    counters: Arc<NicCounters>,
+    #[cfg(feature = "metrics")]
+    probe: Arc<metrics::NicProbe>,
+    umem: Umem,
     xsk: *mut xsk_socket,
 
     /* XSK rings */
-    rxq: xsk_ring_cons,
-    txq: xsk_ring_prod,
-    umem_fq: xsk_ring_prod,
-    umem_cq: xsk_ring_cons,
+    // Boxed, since libxdp keeps pointers to the rings until the XSK is deleted.
+    rxq: Box<xsk_ring_cons>,
+    txq: Box<xsk_ring_prod>,
+    umem_fq: Box<xsk_ring_prod>,
+    umem_cq: Box<xsk_ring_cons>,
+}
+
+/// Packet I/O shared by `pv::Nic` and `pv::offline::OfflineNic`
+///
+/// Processing code generic over `PacketIo` runs unchanged on a network interface
+/// and on pcap files.
+/// ```ignore
+/// fn echo<N: pv::PacketIo>(nic: &mut N) -> usize {
+///     let mut packets = nic.receive(64);
+///     packets.retain_mut(|p| process_packet(p, nic.interface()));
+///     nic.send(&mut packets)
+/// }
+/// ```
+pub trait PacketIo {
+    /// Network interface information. (ex. `interface name`, `L2-3 address`, etc.)
+    fn interface(&self) -> &NetworkInterface;
+
+    /// See `pv::Nic::alloc_packet()`.
+    fn alloc_packet(&self) -> Option<Packet>;
+
+    /// See `pv::Nic::alloc_packets()`.
+    fn alloc_packets(&self, len: usize) -> Option<Vec<Packet>>;
+
+    /// See `pv::Nic::alloc_packets_into()`.
+    fn alloc_packets_into(&self, packets: &mut Vec<Packet>, len: usize) -> usize;
+
+    /// See `pv::Nic::receive()`.
+    fn receive(&mut self, len: usize) -> Vec<Packet>;
+
+    /// See `pv::Nic::receive_timeout()`.
+    fn receive_timeout(&mut self, len: usize, timeout: Duration) -> Vec<Packet>;
+
+    /// See `pv::Nic::send()`.
+    fn send(&mut self, packets: &mut Vec<Packet>) -> usize;
+
+    /// See `pv::Nic::counters()`.
+    fn counters(&self) -> Arc<NicCounters>;
+}
+
+/// Statistics kept by the kernel for the XSK of `pv::Nic`
+///
+/// The values count from the creation of the XSK.
+/// Use `XskStats::delta()` between two reads to get rates.
+/// ```ignore
+/// let before = nic.xsk_stats()?;
+/// thread::sleep(Duration::from_secs(1));
+/// let rate = nic.xsk_stats()?.delta(&before);
+/// if rate.rx_fill_ring_empty_descs > 0 {
+///     // Packets were dropped since the fill ring was empty.
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct XskStats {
+    /// Packets dropped for reasons other than the ones below.
+    pub rx_dropped: u64,
+    /// Packets dropped due to invalid descriptors in the fill ring.
+    pub rx_invalid_descs: u64,
+    /// Packets not sent due to invalid descriptors in the TX ring.
+    pub tx_invalid_descs: u64,
+    /// Packets dropped since the RX ring was full.
+    pub rx_ring_full: u64,
+    /// Packets dropped since the fill ring was empty.
+    pub rx_fill_ring_empty_descs: u64,
+    /// Times the kernel found the TX ring empty when it was woken up to send.
+    pub tx_ring_empty_descs: u64,
+}
+
+/// Counters kept by Packetvisor for `pv::Nic`
+///
+/// Updated with relaxed atomics, so a handle from `Nic::counters()` can be
+/// read from another thread while the `pv::Nic` is in use.
+#[derive(Debug, Default)]
+pub struct NicCounters {
+    rx_packets: AtomicU64,
+    rx_bytes: AtomicU64,
+    tx_packets: AtomicU64,
+    tx_bytes: AtomicU64,
+    tx_partial: AtomicU64,
+    alloc_failures: AtomicU64,
+    fill_shortfall: AtomicU64,
+    rx_wakeups: AtomicU64,
+    tx_wakeups: AtomicU64,
 }
 
 /// Packet Structure used by Packetvisor
+///
+/// A packet can be moved to another thread. Its chunk returns to the UMEM
+/// when it is dropped, regardless of the thread.
 #[derive(Debug)]
 pub struct Packet {
     /// payload offset from `buffer` pointing the start of payload.
@@ -122,7 +394,14 @@ pub struct Packet {
     pub buffer: *mut u8,
     private: *mut c_void, // DO NOT touch this.
 
-    buffer_pool: Rc<RefCell<BufferPool>>,
+    buffer_pool: Arc<BufferPool>,
+}
+
+/// Group of `pv::Nic`s bound to every RX queue of a network interface.
+/// All of them share the same `pv::Umem`.
+#[derive(Debug)]
+pub struct NicGroup {
+    nics: Vec<Nic>,
 }
 
 struct ReservedResult {
@@ -130,6 +409,27 @@ struct ReservedResult {
     idx: u32,
 }
 
+#[repr(C)]
+#[derive(Default)]
+struct EthtoolChannels {
+    cmd: u32,
+    max_rx: u32,
+    max_tx: u32,
+    max_other: u32,
+    max_combined: u32,
+    rx_count: u32,
+    tx_count: u32,
+    other_count: u32,
+    combined_count: u32,
+}
+
+#[repr(C)]
+struct IfReq {
+    ifr_name: [c_char; libc::IF_NAMESIZE],
+    ifr_data: *mut c_void,
+    _pad: [u8; 16], // ifreq union is 24 bytes long.
+}
+
 /********************************************************************
  *
  * Implementation
@@ -140,59 +440,136 @@ impl BufferPool {
         chunk_size: usize,
         chunk_count: usize,
         buffer: *mut c_void,
-        fq_size: usize,
+        buffer_size: usize,
         cq_size: usize,
     ) -> Self {
-        /* initialize UMEM chunk information */
+        /* initialize UMEM chunk information, the lowest address on top */
         let pool = (0..chunk_count)
+            .rev()
             .map(|i| (i * chunk_size).try_into().unwrap())
-            .collect::<HashSet<u64>>();
+            .collect::<Vec<u64>>();
         Self {
             chunk_size,
             chunk_count,
-            pool,
+            pool: Mutex::new(pool),
+            returned: ArrayQueue::new(chunk_count),
+            fill_owner: (0..chunk_count).map(|_| AtomicU32::new(0)).collect(),
+            fq_ids: AtomicU32::new(0),
             buffer,
-            fq_size,
+            buffer_size,
             cq_size,
         }
     }
 
-    fn alloc_addr(&mut self) -> Result<u64, &'static str> {
-        if let Some(addr) = self.pool.iter().next() {
-            let addr = *addr;
-            self.pool.remove(&addr);
-            Ok(addr)
-        } else {
-            Err("Chunk Pool is empty")
+    fn alloc_addr(&self) -> Result<u64, Error> {
+        let mut pool = self.pool.lock().unwrap();
+        if pool.is_empty() {
+            self.collect(&mut pool);
         }
+
+        pool.pop().ok_or(Error::PoolEmpty)
     }
 
-    fn free_addr(&mut self, chunk_addr: u64) {
+    /// Lock-free, so packets can be dropped on any thread.
+    fn free_addr(&self, chunk_addr: u64) {
         // Align
         let chunk_addr = chunk_addr - (chunk_addr % self.chunk_size as u64);
 
-        #[cfg(debug_assertions)]
-        if self.pool.contains(&chunk_addr) {
-            eprintln!("Chunk Pool already contains chunk_addr: {}", chunk_addr);
+        if self.returned.push(chunk_addr).is_err() {
+            eprintln!("Chunk Pool is overflowed");
         }
+    }
 
-        self.pool.insert(chunk_addr);
+    /// Move the chunks freed by packets back into the pool at once.
+    fn collect(&self, pool: &mut MutexGuard<Vec<u64>>) {
+        while let Some(chunk_addr) = self.returned.pop() {
+            pool.push(chunk_addr);
+        }
 
         #[cfg(debug_assertions)]
-        if self.pool.len() > self.chunk_count {
+        if pool.len() > self.chunk_count {
             eprintln!("Chunk Pool is overflowed");
         }
     }
 
+    /// New id for the FQ of an XSK, to tell which chunks it holds.
+    fn new_fq_id(&self) -> u32 {
+        self.fq_ids.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Entry of `fill_owner` for the chunk containing `addr`.
+    fn fill_owner(&self, addr: u64) -> &AtomicU32 {
+        &self.fill_owner[(addr / self.chunk_size as u64) as usize]
+    }
+
+    /// Number of chunks not owned by the kernel or the application.
+    fn free_count(&self) -> usize {
+        let mut pool = self.pool.lock().unwrap();
+        self.collect(&mut pool);
+        pool.len()
+    }
+
+    /// Allocate packet from UMEM
+    fn try_alloc_packet(self: &Arc<Self>) -> Option<Packet> {
+        match self.alloc_addr() {
+            Err(_) => None,
+            Ok(idx) => Some(self.packet(idx)),
+        }
+    }
+
+    /// Allocate up to `len` packets from UMEM under a single lock.
+    /// If `exact`, nothing is allocated unless all `len` packets are available.
+    fn alloc_packets(
+        self: &Arc<Self>,
+        packets: &mut Vec<Packet>,
+        len: usize,
+        exact: bool,
+    ) -> usize {
+        let mut pool = self.pool.lock().unwrap();
+        if pool.len() < len {
+            self.collect(&mut pool);
+        }
+        if exact && pool.len() < len {
+            return 0;
+        }
+
+        let count = len.min(pool.len());
+        let at = pool.len() - count;
+        packets.reserve(count);
+        packets.extend(pool.drain(at..).map(|idx| self.packet(idx)));
+
+        count
+    }
+
+    /// Empty packet on the chunk at `idx`
+    fn packet(self: &Arc<Self>, idx: u64) -> Packet {
+        let mut packet: Packet = Packet::new(self);
+        packet.buffer_size = self.chunk_size;
+        packet.buffer = unsafe { xsk_umem__get_data(self.buffer, idx) as *mut u8 };
+        packet.private = idx as *mut c_void;
+
+        packet
+    }
+
     /// Reserve FQ and UMEM chunks as much as **len
-    fn reserve_fq(&mut self, fq: &mut xsk_ring_prod, len: usize) -> Result<usize, &'static str> {
+    /// The chunks are taken from the pool under a single lock, and marked as held by `fq_id`.
+    /// Fewer than `len` are reserved if the pool or the ring runs short.
+    fn reserve_fq(&self, fq: &mut xsk_ring_prod, fq_id: u32, len: usize) -> usize {
+        let mut pool = self.pool.lock().unwrap();
+        if pool.len() < len {
+            self.collect(&mut pool);
+        }
+
         let mut cq_idx = 0;
-        let reserved = unsafe { xsk_ring_prod__reserve(fq, len as u32, &mut cq_idx) };
+        let reserved =
+            unsafe { xsk_ring_prod__reserve(fq, len.min(pool.len()) as u32, &mut cq_idx) };
 
         // Allocate UMEM chunks into fq
-        for i in 0..reserved {
+        let at = pool.len() - reserved as usize;
+        for (i, addr) in pool.drain(at..).enumerate() {
+            self.fill_owner(addr).store(fq_id, Ordering::Relaxed);
             unsafe {
-                *xsk_ring_prod__fill_addr(fq, cq_idx + i) = self.alloc_addr()?;
+                *xsk_ring_prod__fill_addr(fq, cq_idx + i as u32) = addr;
             }
         }
 
@@ -201,45 +578,86 @@ impl BufferPool {
             xsk_ring_prod__submit(fq, reserved);
         }
 
-        Ok(reserved.try_into().unwrap())
+        reserved.try_into().unwrap()
+    }
+
+    /// Refill FQ up to `watermark` entries with the chunks available.
+    /// # Returns
+    /// (Number of chunks added, Number of chunks missing to reach `watermark`)
+    fn refill_fq(&self, fq: &mut xsk_ring_prod, fq_id: u32, watermark: usize) -> (usize, usize) {
+        let filled = unsafe { fq.size - xsk_prod_nb_free(fq, fq.size) } as usize;
+        let wanted = watermark.saturating_sub(filled);
+        if wanted == 0 {
+            return (0, 0);
+        }
+
+        let refilled = self.reserve_fq(fq, fq_id, wanted);
+        (refilled, wanted - refilled)
     }
 
     /// Reserve for txq
-    fn reserve_txq(
-        &mut self,
-        txq: &mut xsk_ring_prod,
-        len: usize,
-    ) -> Result<ReservedResult, &'static str> {
+    fn reserve_txq(&self, txq: &mut xsk_ring_prod, len: usize) -> ReservedResult {
         let mut idx = 0;
         let count = unsafe { xsk_ring_prod__reserve(txq, len as u32, &mut idx) };
 
-        Ok(ReservedResult { count, idx })
+        ReservedResult { count, idx }
     }
 
     /// Free packet metadata and UMEM chunks as much as the # of filled slots in CQ
-    fn release(&mut self, cq: &mut xsk_ring_cons, len: usize) -> Result<u32, String> {
+    /// The chunks are returned to the pool under a single lock.
+    fn release(&self, cq: &mut xsk_ring_cons, len: usize) -> u32 {
         let mut cq_idx = 0;
         let count = unsafe {
             // Fetch the number of filled slots(the # of packets completely sent) in cq
             xsk_ring_cons__peek(cq, len as u32, &mut cq_idx)
         };
         if count > 0 {
+            let mut pool = self.pool.lock().unwrap();
+            for i in 0..count {
+                let chunk_addr = unsafe { *xsk_ring_cons__comp_addr(cq, cq_idx + i) };
+                // Align
+                pool.push(chunk_addr - (chunk_addr % self.chunk_size as u64));
+            }
+            drop(pool);
+
             // Notify kernel that cq has empty slots with **filled (Dequeue)
             unsafe {
                 xsk_ring_cons__release(cq, count);
             }
         }
 
-        Ok(count)
+        count
+    }
+
+    /// Take back the chunks held by the FQ of a deleted XSK.
+    /// # Description
+    /// Every chunk filled into the FQ is owned by `fq_id` until it is received, whether
+    /// it is still in the FQ, held by the driver or waiting in the RX ring. Once the XSK
+    /// is deleted, the kernel no longer writes to any of them, so the ownership table
+    /// alone tells which chunks to return, without reading the rings.
+    /// # Returns
+    /// Number of chunks returned to the pool
+    fn reclaim(&self, fq_id: u32) -> usize {
+        let mut pool = self.pool.lock().unwrap();
+        let mut reclaimed = 0;
+        for (i, owner) in self.fill_owner.iter().enumerate() {
+            if owner
+                .compare_exchange(fq_id, 0, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+            {
+                pool.push((i * self.chunk_size) as u64);
+                reclaimed += 1;
+            }
+        }
+
+        reclaimed
     }
 
     fn recv(
-        &mut self,
-        chunk_pool_rc: &Rc<RefCell<Self>>,
+        self: &Arc<Self>,
         len: usize,
-        _xsk: &*mut xsk_socket,
         rxq: &mut xsk_ring_cons,
-        fq: &mut xsk_ring_prod,
+        counters: &NicCounters,
     ) -> Vec<Packet> {
         let mut packets = Vec::<Packet>::with_capacity(len);
 
@@ -250,10 +668,13 @@ impl BufferPool {
             return packets;
         }
 
+        let mut bytes = 0;
         for i in 0..received {
-            let mut packet = Packet::new(chunk_pool_rc);
+            let mut packet = Packet::new(self);
             let rx_desc = unsafe { xsk_ring_cons__rx_desc(&*rxq, rx_idx + i).as_ref().unwrap() };
+            self.fill_owner(rx_desc.addr).store(0, Ordering::Relaxed);
             packet.end += rx_desc.len as usize;
+            bytes += rx_desc.len as u64;
             packet.buffer_size = self.chunk_size;
             packet.buffer = unsafe {
                 xsk_umem__get_data(self.buffer, rx_desc.addr)
@@ -269,41 +690,26 @@ impl BufferPool {
             xsk_ring_cons__release(rxq, received);
         }
 
-        self.reserve_fq(fq, packets.len()).unwrap();
-
-        /*
-         * XSK manages interrupts through xsk_ring_prod__needs_wakup().
-         *
-         * If Packetvisor is assigned to core indexes [0] or [1], the Rx interrupt does not work properly.
-         * This significantly degrades Packetvisor performance.
-         * To resolve this issue, the interrupt is woken up whenever Recv() is called.
-         */
-        unsafe {
-            if xsk_ring_prod__needs_wakeup(&*fq) != 0 {
-                libc::recvfrom(
-                    xsk_socket__fd(*_xsk),
-                    std::ptr::null_mut::<libc::c_void>(),
-                    0 as libc::size_t,
-                    libc::MSG_DONTWAIT,
-                    std::ptr::null_mut::<libc::sockaddr>(),
-                    std::ptr::null_mut::<u32>(),
-                );
-            }
-        }
+        NicCounters::add(&counters.rx_packets, received as u64);
+        NicCounters::add(&counters.rx_bytes, bytes);
 
         packets
     }
 
     fn send(
-        &mut self,
-        packets: &mut [Packet],
+        &self,
+        packets: &mut Vec<Packet>,
         xsk: &*mut xsk_socket,
         tx: &mut xsk_ring_prod,
-        cq: &mut xsk_ring_cons,
+        busy_poll: bool,
+        counters: &NicCounters,
     ) -> usize {
-        self.release(cq, self.cq_size).unwrap();
-        let reserved = self.reserve_txq(tx, packets.len()).unwrap();
+        let reserved = self.reserve_txq(tx, packets.len());
+        if (reserved.count as usize) < packets.len() {
+            NicCounters::add(&counters.tx_partial, 1);
+        }
 
+        let mut bytes = 0;
         for (i, pkt) in packets.iter().enumerate().take(reserved.count as usize) {
             // Insert packets to be sent into the TX ring (Enqueue)
             let tx_desc = unsafe {
@@ -313,108 +719,68 @@ impl BufferPool {
             };
             tx_desc.addr = pkt.private as u64 + pkt.start as u64;
             tx_desc.len = (pkt.end - pkt.start) as u32;
+            bytes += tx_desc.len as u64;
         }
 
-        // NOTE: Dropping packet here will cause Already Borrowed error.
-        // So, we should drain packets after this function call.
-        // packets.drain(0..reserved.count as usize);
+        // The kernel owns the chunks until they come back on the completion ring.
+        for packet in packets.drain(0..reserved.count as usize) {
+            packet.into_inflight();
+        }
 
         unsafe {
             xsk_ring_prod__submit(&mut *tx, reserved.count);
-            if xsk_ring_prod__needs_wakeup(tx) != 0 {
-                // Interrupt the kernel to send packets
-                libc::sendto(
-                    xsk_socket__fd(*xsk),
-                    std::ptr::null::<libc::c_void>(),
-                    0 as libc::size_t,
-                    libc::MSG_DONTWAIT,
-                    std::ptr::null::<libc::sockaddr>(),
-                    0 as libc::socklen_t,
-                );
-            }
         }
+        if busy_poll || unsafe { xsk_ring_prod__needs_wakeup(tx) } != 0 {
+            // Interrupt the kernel to send packets
+            wakeup_tx(*xsk);
+            NicCounters::add(&counters.tx_wakeups, 1);
+        }
+
+        NicCounters::add(&counters.tx_packets, reserved.count as u64);
+        NicCounters::add(&counters.tx_bytes, bytes);
 
         reserved.count.try_into().unwrap()
     }
 }
 
 impl Pool {
-    fn new() -> Result<Self, String> {
-        let umem_ptr = alloc_zeroed_layout::<xsk_umem>()?;
-        let fq_ptr = alloc_zeroed_layout::<xsk_ring_prod>()?;
-        let cq_ptr = alloc_zeroed_layout::<xsk_ring_cons>()?;
-
-        let umem = umem_ptr.cast::<xsk_umem>(); // umem is needed to be dealloc after using packetvisor library.
-        let fq = unsafe { std::ptr::read(fq_ptr.cast::<xsk_ring_prod>()) };
-        let cq = unsafe { std::ptr::read(cq_ptr.cast::<xsk_ring_cons>()) };
-
-        let chunk_pool = BufferPool::new(0, 0, std::ptr::null_mut(), 0, 0);
-
-        let chunk_size = 0;
-        let refcount = 0;
-
-        let obj = Self {
-            chunk_size,
-            umem,
-            buffer_pool: Rc::new(RefCell::new(chunk_pool)),
-            umem_fq: fq,
-            umem_cq: cq,
-            refcount,
+    /// Map the buffer, and register it to the kernel unless `offline`.
+    fn new(config: &UmemConfig, offline: bool) -> Result<Self, Error> {
+        let (mmap_address, buffer_size, page_size) = map_umem(config)?;
+
+        // From here on, the buffer is unmapped when the BufferPool is dropped.
+        let chunk_pool = BufferPool::new(
+            config.chunk_size,
+            config.chunk_count,
+            mmap_address,
+            buffer_size,
+            config.cq_size,
+        );
+
+        let mut pool = Self {
+            config: config.clone(),
+            page_size,
+            offline,
+            umem: std::ptr::null_mut(),
+            buffer_pool: Arc::new(chunk_pool),
+            umem_fq: Box::new(unsafe { std::mem::zeroed() }),
+            umem_cq: Box::new(unsafe { std::mem::zeroed() }),
+            refcount: 0,
         };
 
-        Ok(obj)
-    }
-
-    fn instance() -> *mut Self {
-        static INSTANCE: std::sync::OnceLock<Pool> = std::sync::OnceLock::new();
-
-        INSTANCE.get_or_init(|| Self::new().unwrap());
-
-        INSTANCE.get().unwrap() as *const _ as *mut _
-    }
-
-    fn init(
-        chunk_size: usize,
-        chunk_count: usize,
-        fq_size: usize,
-        cq_size: usize,
-    ) -> Result<(), String> {
-        unsafe {
-            let pool = Pool::instance();
-            if (*pool).refcount == 0 {
-                (*pool).re_init(chunk_size, chunk_count, fq_size, cq_size)?;
-            }
+        if !offline {
+            pool.create()?;
         }
-        Ok(())
-    }
-
-    fn re_init(
-        &mut self,
-        chunk_size: usize,
-        chunk_count: usize,
-        fq_size: usize,
-        cq_size: usize,
-    ) -> Result<(), String> {
-        let umem_buffer_size = chunk_size * chunk_count;
-        let mmap_address = unsafe {
-            libc::mmap(
-                std::ptr::null_mut::<libc::c_void>(),
-                umem_buffer_size,
-                libc::PROT_READ | libc::PROT_WRITE,
-                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
-                -1, // fd
-                0,  // offset
-            )
-        };
 
-        if mmap_address == libc::MAP_FAILED {
-            return Err("Failed to allocate memory for UMEM.".to_string());
-        }
+        Ok(pool)
+    }
 
+    /// Register the buffer to the kernel as UMEM.
+    fn create(&mut self) -> Result<(), Error> {
         let umem_cfg = xsk_umem_config {
-            fill_size: fq_size as u32,
-            comp_size: cq_size as u32,
-            frame_size: chunk_size as u32,
+            fill_size: self.config.fq_size as u32,
+            comp_size: self.config.cq_size as u32,
+            frame_size: self.config.chunk_size as u32,
             frame_headroom: XSK_UMEM__DEFAULT_FRAME_HEADROOM,
             flags: XSK_UMEM__DEFAULT_FLAGS,
         };
@@ -422,313 +788,1107 @@ impl Pool {
         let ret = unsafe {
             xsk_umem__create(
                 &mut self.umem,
-                mmap_address,
-                umem_buffer_size as u64,
-                &mut self.umem_fq,
-                &mut self.umem_cq,
+                self.buffer_pool.buffer,
+                (self.config.chunk_size * self.config.chunk_count) as u64,
+                &mut *self.umem_fq,
+                &mut *self.umem_cq,
                 &umem_cfg,
             )
         };
 
         if ret != 0 {
-            unsafe {
-                // Unmap first
-                libc::munmap(mmap_address, umem_buffer_size);
-            }
-            let msg = unsafe {
-                CStr::from_ptr(strerror(-ret))
-                    .to_string_lossy()
-                    .into_owned()
-            };
-
-            return Err(format!("Failed to create UMEM: {}", msg));
+            self.umem = std::ptr::null_mut();
+            return Err(Error::UmemCreate(Error::from_errno(ret)));
         }
 
-        let chunk_pool = BufferPool::new(chunk_size, chunk_count, mmap_address, fq_size, cq_size);
-        let mut borrow_buffer_pool = self.buffer_pool.borrow_mut();
-        *borrow_buffer_pool = chunk_pool;
-
-        self.chunk_size = chunk_size;
-
         Ok(())
     }
 
-    fn alloc_addr(&mut self) -> Result<u64, &'static str> {
-        self.buffer_pool.borrow_mut().alloc_addr()
+    /// Delete the UMEM and register the same buffer again.
+    /// Only possible while no XSK uses the UMEM.
+    fn re_create(&mut self) -> Result<(), Error> {
+        unsafe { xsk_umem__delete(self.umem) };
+        self.umem = std::ptr::null_mut();
+        thread::sleep(Duration::from_millis(100));
+
+        self.create()
     }
+}
 
-    /// Allocate packet from UMEM
-    fn try_alloc_packet(&mut self) -> Option<Packet> {
-        match self.alloc_addr() {
-            Err(_) => None,
-            Ok(idx) => {
-                let mut packet: Packet = Packet::new(&self.buffer_pool);
-                packet.buffer_size = self.chunk_size;
-                packet.buffer =
-                    unsafe { xsk_umem__get_data(self.buffer_pool.borrow().buffer, idx) as *mut u8 };
-                packet.private = idx as *mut c_void;
-
-                Some(packet)
-            }
+impl Default for UmemConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: 2048,
+            chunk_count: 1024,
+            fq_size: 64,
+            cq_size: 64,
+            page_size: PageSize::Default,
+            hugepage_fallback: true,
+            memfd: false,
+            numa_node: None,
         }
     }
 }
 
-unsafe impl Send for Pool {}
-unsafe impl Sync for Pool {}
+impl UmemConfig {
+    /// Size of a UMEM chunk. Must be a power of two between 2048 and the page size.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
 
-impl Nic {
-    /// # Description
-    /// Attaching `pv::Nic` to network interface
-    /// # Arguments
-    /// `if_name` - network interface name \
-    /// `chunk_size` - total size of packet payload \
-    /// `chunk_count` - total count of chunk \
-    /// `fq_size` - filling ring size \
-    /// `cq_size` - completion ring size \
-    /// `tx_size` - tx ring size \
-    /// `rx_size` - rx ring size \
-    /// # Returns
-    /// On success, returns `pv::Nic` bound to the network interface. \
-    /// On failure, returns an error string.
-    pub fn new(
-        if_name: &str,
-        chunk_size: usize,
-        chunk_count: usize,
-        fq_size: usize,
-        cq_size: usize,
-        tx_size: usize,
-        rx_size: usize,
-    ) -> Result<Nic, String> {
-        let interface = interfaces()
-            .into_iter()
-            .find(|elem| elem.name.as_str() == if_name)
-            .ok_or(format!("Interface {} not found.", if_name))?;
-
-        let xsk_ptr = alloc_zeroed_layout::<xsk_socket>()?;
-        let rx_ptr = alloc_zeroed_layout::<xsk_ring_cons>()?;
-        let tx_ptr = alloc_zeroed_layout::<xsk_ring_prod>()?;
-        let fq_ptr = alloc_zeroed_layout::<xsk_ring_prod>()?;
-        let cq_ptr = alloc_zeroed_layout::<xsk_ring_cons>()?;
-
-        /* The result of Pool::init() must be unwrapped using the unwrap() function. \
-         * If you do not use unwrap(), the internal fields of the Pool object will not \
-         * be properly initialized, which can lead to potential problems.
-         *
-         * Ex) Fallback between XSK's SKB and DRV modes may not be possible. \
-         *     Other unexpected problems may occur. */
-        Pool::init(chunk_size, chunk_count, fq_size, cq_size).unwrap();
+    /// Total count of UMEM chunks.
+    pub fn chunk_count(mut self, chunk_count: usize) -> Self {
+        self.chunk_count = chunk_count;
+        self
+    }
 
-        let mut nic = unsafe {
-            Nic {
-                interface: interface.clone(),
-                xsk: xsk_ptr.cast::<xsk_socket>(),
-                rxq: std::ptr::read(rx_ptr.cast::<xsk_ring_cons>()),
-                txq: std::ptr::read(tx_ptr.cast::<xsk_ring_prod>()),
-                umem_fq: std::ptr::read(fq_ptr.cast::<xsk_ring_prod>()),
-                umem_cq: std::ptr::read(cq_ptr.cast::<xsk_ring_cons>()),
-            }
-        };
+    /// Filling ring size of each `pv::Nic`. Must be a power of two.
+    pub fn fill_ring_size(mut self, fq_size: usize) -> Self {
+        self.fq_size = fq_size;
+        self
+    }
 
-        match Nic::open(
-            &mut nic,
-            chunk_size,
-            chunk_count,
-            fq_size,
-            cq_size,
-            tx_size,
-            rx_size,
-        ) {
-            Ok(_) => {
-                unsafe {
-                    let pool = Pool::instance();
-                    (*pool).refcount += 1;
-                }
-                Ok(nic)
-            }
-            Err(e) => {
-                // FIXME: Print here is fine. But segfault happened when printing in the caller.
-                eprintln!("Failed to open NIC: {}", e);
-                Err(e)
-            }
-        }
+    /// Completion ring size of each `pv::Nic`. Must be a power of two.
+    pub fn completion_ring_size(mut self, cq_size: usize) -> Self {
+        self.cq_size = cq_size;
+        self
     }
 
-    fn open(
-        &mut self,
-        chunk_size: usize,
-        chunk_count: usize,
-        fq_size: usize,
-        cq_size: usize,
-        rx_ring_size: usize,
-        tx_ring_size: usize,
-    ) -> Result<(), String> {
-        let mut xsk_cfg: xsk_socket_config = xsk_socket_config {
-            rx_size: rx_ring_size.try_into().unwrap(),
-            tx_size: tx_ring_size.try_into().unwrap(),
-            __bindgen_anon_1: xsk_socket_config__bindgen_ty_1 { libxdp_flags: 0 },
-            xdp_flags: XDP_FLAGS_DRV_MODE,
-            bind_flags: XDP_USE_NEED_WAKEUP as u16,
-        };
-        let if_name = CString::new(self.interface.name.clone()).unwrap();
-        let if_ptr = if_name.as_ptr() as *const c_char;
+    /// Page size backing the UMEM buffer. Huge pages reduce TLB misses on large UMEMs. \
+    /// The buffer is rounded up to a multiple of the page size.
+    pub fn page_size(mut self, page_size: PageSize) -> Self {
+        self.page_size = page_size;
+        self
+    }
 
-        let ret: c_int = unsafe {
-            xsk_socket__create_shared(
-                &mut self.xsk,
-                if_ptr,
-                0,
-                (*Pool::instance()).umem,
-                &mut self.rxq,
-                &mut self.txq,
-                &mut self.umem_fq,
-                &mut self.umem_cq,
-                &xsk_cfg,
-            )
-        };
+    /// Fall back to regular pages if huge pages cannot be allocated. Default is `true`. \
+    /// If `false`, `Umem::new()` fails instead. `Umem::page_size()` reports the page size in use.
+    pub fn hugepage_fallback(mut self, fallback: bool) -> Self {
+        self.hugepage_fallback = fallback;
+        self
+    }
 
-        if ret != 0 {
-            match unsafe {
-                let pool = Pool::instance();
-                (*pool).refcount
-            } {
-                0 => {
-                    // Pool Full-Fallback
-                    unsafe { xsk_umem__delete((*Pool::instance()).umem) };
-                    thread::sleep(Duration::from_millis(100));
-
-                    Pool::init(chunk_size, chunk_count, fq_size, cq_size)?;
-                }
-                refcount if refcount > 0 => {
-                    // Pool Semi-Fallback
-                    thread::sleep(Duration::from_millis(100));
-                }
-                _ => {
-                    return Err("Pool Fallback failed".to_string());
-                }
-            }
+    /// Back the UMEM buffer with a memfd instead of anonymous memory.
+    pub fn memfd(mut self, memfd: bool) -> Self {
+        self.memfd = memfd;
+        self
+    }
 
-            xsk_cfg.xdp_flags = XDP_FLAGS_SKB_MODE;
-            let ret: c_int = unsafe {
-                let pool = Pool::instance();
-                xsk_socket__create_shared(
-                    &mut self.xsk,
-                    if_ptr,
-                    0,
-                    (*pool).umem,
-                    &mut self.rxq,
-                    &mut self.txq,
-                    &mut (*pool).umem_fq,
-                    &mut (*pool).umem_cq,
-                    &xsk_cfg,
-                )
-            };
+    /// Allocate the UMEM buffer only on the given NUMA node. \
+    /// Use the node of the NIC (`/sys/class/net/<if>/device/numa_node`) to avoid cross-node DMA.
+    pub fn numa_node(mut self, node: usize) -> Self {
+        self.numa_node = Some(node);
+        self
+    }
 
-            if ret != 0 {
-                let msg = unsafe {
-                    CStr::from_ptr(strerror(-ret))
-                        .to_string_lossy()
-                        .into_owned()
-                };
-                let message = format!("Error: {}", msg);
-                return Err(format!("xsk_socket__create failed: {}", message));
+    /// # Description
+    /// Check the configuration before the UMEM is created.
+    /// # Returns
+    /// On success, returns `Ok(())`. \
+    /// On failure, returns `pv::Error::InvalidConfig` describing the invalid value.
+    pub fn validate(&self) -> Result<(), Error> {
+        for (name, size) in [
+            ("fill ring", self.fq_size),
+            ("completion ring", self.cq_size),
+        ] {
+            if !size.is_power_of_two() {
+                return Err(Error::InvalidConfig(format!(
+                    "The {} size must be a power of two. (Got {})",
+                    name, size
+                )));
             }
         }
 
-        /*
-         * After calling xsk_umem__create(), the fill_q and comp_q of the UMEM are initialized.
-         * These are assigned to the first XSK through xsk_socket__create() or  _shared().
-         * However, this does not work properly in Rust.
-         *
-         * Therefore, we stored the fill_q and comp_q in the Pool object before calling xsk_umem__create().
-         * After xsk_socket__create() or _shared() finishes, the stored fill_q and comp_q are assigned to the Nic object.
-         * This resolves the Segmentation Fault issue.
-         */
-        if unsafe {
-            let pool = Pool::instance();
-            (*pool).refcount == 0
-        } {
-            let pool = Pool::instance();
-            unsafe {
-                self.umem_fq = (*pool).umem_fq;
-                self.umem_cq = (*pool).umem_cq;
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        if !self.chunk_size.is_power_of_two()
+            || self.chunk_size < MIN_CHUNK_SIZE
+            || self.chunk_size > page_size
+        {
+            return Err(Error::InvalidConfig(format!(
+                "The chunk size must be a power of two between {} and {}. (Got {})",
+                MIN_CHUNK_SIZE, page_size, self.chunk_size
+            )));
+        }
 
-                let fq_ptr = alloc_zeroed_layout::<xsk_ring_prod>()?;
-                let cq_ptr = alloc_zeroed_layout::<xsk_ring_cons>()?;
-                (*pool).umem_fq = std::ptr::read(fq_ptr.cast::<xsk_ring_prod>());
-                (*pool).umem_cq = std::ptr::read(cq_ptr.cast::<xsk_ring_cons>());
-            };
+        for (name, size) in [
+            ("fill ring", self.fq_size),
+            ("completion ring", self.cq_size),
+        ] {
+            if self.chunk_count < size {
+                return Err(Error::InvalidConfig(format!(
+                    "The chunk count must cover the {}. (Min = {}, Got {})",
+                    name, size, self.chunk_count
+                )));
+            }
         }
 
-        unsafe {
-            let pool = Pool::instance();
-            let fq_size = (*pool).buffer_pool.borrow().fq_size;
-            (*pool)
-                .buffer_pool
-                .borrow_mut()
-                .reserve_fq(&mut self.umem_fq, fq_size)?;
+        if let Some(node) = self.numa_node {
+            if node >= MAX_NUMA_NODES {
+                return Err(Error::InvalidConfig(format!(
+                    "The NUMA node must be less than {}. (Got {})",
+                    MAX_NUMA_NODES, node
+                )));
+            }
         }
 
         Ok(())
     }
 
-    /// # Description
-    /// Allocate packet using Pool
-    /// # Returns
-    /// On success, returns `pv::Packet` with empty payload. \
-    /// On failure, returns `None`.
-    pub fn alloc_packet(&self) -> Option<Packet> {
-        unsafe { (*Pool::instance()).try_alloc_packet() }
+    /// Check that a frame of `mtu` fits in a chunk after the reserved headroom.
+    fn validate_mtu(&self, mtu: usize) -> Result<(), Error> {
+        let frame_size = mtu + ETH_HEADER_SIZE;
+        let available = self.chunk_size - DEFAULT_HEADROOM;
+        if frame_size > available {
+            return Err(Error::InvalidConfig(format!(
+                "MTU {} does not fit in the chunk. (Frame = {}, Available = {})",
+                mtu, frame_size, available
+            )));
+        }
+
+        Ok(())
     }
+}
 
+impl Umem {
     /// # Description
-    /// Send packets \
-    /// **\*Sent packets are removed from the vector.**
+    /// Allocate a buffer and register it to the kernel as UMEM
     /// # Arguments
-    /// `packets` - Packets to send
+    /// `config` - UMEM geometry. See `pv::UmemConfig`.
     /// # Returns
-    /// Number of packets sent
-    pub fn send(&mut self, packets: &mut Vec<Packet>) -> usize {
-        let pool = Pool::instance();
-        let sent_count = unsafe {
-            (*pool).buffer_pool.borrow_mut().send(
-                packets,
-                &self.xsk,
-                &mut self.txq,
-                &mut self.umem_cq,
-            )
-        };
-        packets.drain(0..sent_count);
-
-        sent_count
+    /// On success, returns `pv::Umem`. \
+    /// On failure, returns `pv::Error`.
+    pub fn new(config: &UmemConfig) -> Result<Umem, Error> {
+        Umem::create(config, false)
     }
 
     /// # Description
-    /// Receive packets
+    /// Allocate a buffer without registering it to the kernel. \
+    /// Needs no privilege, but only works with `pv::offline::OfflineNic`.
     /// # Arguments
-    /// `len` - Number of packets to receive
+    /// `config` - UMEM geometry. See `pv::UmemConfig`.
     /// # Returns
-    /// Received packets
-    pub fn receive(&mut self, len: usize) -> Vec<Packet> {
-        let pool = Pool::instance();
-        unsafe {
-            (*(*pool).buffer_pool).borrow_mut().recv(
-                &(*pool).buffer_pool,
-                len,
-                &self.xsk,
-                &mut self.rxq,
-                &mut self.umem_fq,
-            )
-        }
+    /// On success, returns `pv::Umem`. \
+    /// On failure, returns `pv::Error`.
+    pub fn offline(config: &UmemConfig) -> Result<Umem, Error> {
+        Umem::create(config, true)
     }
-}
 
-impl Packet {
-    fn new(chunk_pool: &Rc<RefCell<BufferPool>>) -> Packet {
-        Packet {
-            start: DEFAULT_HEADROOM,
-            end: DEFAULT_HEADROOM,
-            buffer_size: 0,
+    fn create(config: &UmemConfig, offline: bool) -> Result<Umem, Error> {
+        config.validate()?;
+
+        let pool = Pool::new(config, offline)?;
+        let buffer_pool = pool.buffer_pool.clone();
+
+        Ok(Umem {
+            pool: Arc::new(Mutex::new(pool)),
+            buffer_pool,
+        })
+    }
+
+    /// Whether the UMEM is not registered to the kernel. See `Umem::offline()`.
+    pub fn is_offline(&self) -> bool {
+        self.pool.lock().unwrap().offline
+    }
+
+    /// Size of a UMEM chunk.
+    pub fn chunk_size(&self) -> usize {
+        self.buffer_pool.chunk_size
+    }
+
+    /// Total count of UMEM chunks.
+    pub fn chunk_count(&self) -> usize {
+        self.buffer_pool.chunk_count
+    }
+
+    /// # Description
+    /// Number of free chunks, neither held by the application nor given to the kernel. \
+    /// Equals `chunk_count()` once every `pv::Packet` and `pv::Nic` on the UMEM is dropped.
+    pub fn free_chunks(&self) -> usize {
+        self.buffer_pool.free_count()
+    }
+
+    /// Page size actually backing the UMEM buffer, after any fallback.
+    pub fn page_size(&self) -> PageSize {
+        self.pool.lock().unwrap().page_size
+    }
+
+    /// # Description
+    /// Allocate packet from the UMEM
+    /// # Returns
+    /// On success, returns `pv::Packet` with empty payload. \
+    /// On failure, returns `None`.
+    pub fn alloc_packet(&self) -> Option<Packet> {
+        self.buffer_pool.try_alloc_packet()
+    }
+
+    /// # Description
+    /// Allocate `len` packets from the UMEM at once
+    /// # Arguments
+    /// `len` - Number of packets to allocate
+    /// # Returns
+    /// On success, returns `len` `pv::Packet`s with empty payload. \
+    /// If fewer than `len` chunks are free, returns `None` and allocates nothing.
+    pub fn alloc_packets(&self, len: usize) -> Option<Vec<Packet>> {
+        let mut packets = Vec::with_capacity(len);
+        match self.buffer_pool.alloc_packets(&mut packets, len, true) {
+            0 if len > 0 => None,
+            _ => Some(packets),
+        }
+    }
+
+    /// # Description
+    /// Allocate as many packets as possible, up to `len`, from the UMEM at once
+    /// # Arguments
+    /// `packets` - Vector to append the allocated packets to \
+    /// `len` - Maximum number of packets to allocate
+    /// # Returns
+    /// Number of packets appended to `packets`
+    pub fn alloc_packets_into(&self, packets: &mut Vec<Packet>, len: usize) -> usize {
+        self.buffer_pool.alloc_packets(packets, len, false)
+    }
+
+    fn config(&self) -> UmemConfig {
+        self.pool.lock().unwrap().config.clone()
+    }
+}
+
+impl Default for NicConfig {
+    fn default() -> Self {
+        Self {
+            tx_size: 64,
+            rx_size: 64,
+            queue_id: 0,
+            xdp_mode: XdpMode::Auto,
+            bind_mode: BindMode::Auto,
+            xdp_program: None,
+            divert: None,
+            chain: ChainConfig::default(),
+            busy_poll: None,
+            fill_watermark: None,
+        }
+    }
+}
+
+impl NicConfig {
+    /// TX ring size. Must be a power of two.
+    pub fn tx_ring_size(mut self, tx_size: usize) -> Self {
+        self.tx_size = tx_size;
+        self
+    }
+
+    /// RX ring size. Must be a power of two.
+    pub fn rx_ring_size(mut self, rx_size: usize) -> Self {
+        self.rx_size = rx_size;
+        self
+    }
+
+    /// RX queue of the network interface to bind to.
+    pub fn queue_id(mut self, queue_id: u32) -> Self {
+        self.queue_id = queue_id;
+        self
+    }
+
+    /// XDP attach mode. `Native` and `Generic` fail instead of falling back.
+    pub fn xdp_mode(mut self, xdp_mode: XdpMode) -> Self {
+        self.xdp_mode = xdp_mode;
+        self
+    }
+
+    /// XSK bind mode. `ZeroCopy` fails if the driver does not support it.
+    pub fn bind_mode(mut self, bind_mode: BindMode) -> Self {
+        self.bind_mode = bind_mode;
+        self
+    }
+
+    /// XDP program to attach instead of the libxdp default redirect program.
+    /// `pv::NicGroup` attaches it once and registers every queue in its `XSKMAP`.
+    pub fn xdp_program(mut self, xdp_program: XdpProgram) -> Self {
+        self.xdp_program = Some(xdp_program);
+        self
+    }
+
+    /// Divert only packets matching `filter`. The rest go to the kernel network stack.
+    pub fn divert(mut self, filter: Match) -> Self {
+        self.divert = Some(filter);
+        self
+    }
+
+    /// Run priority in the libxdp dispatcher. Lower values run first. Default is 50.
+    pub fn run_priority(mut self, run_priority: u32) -> Self {
+        self.chain.run_priority = Some(run_priority);
+        self
+    }
+
+    /// Whether the next program in the libxdp dispatcher runs after `action`.
+    /// By default, only `XdpAction::Pass` continues the chain.
+    pub fn chain_call(mut self, action: XdpAction, enabled: bool) -> Self {
+        self.chain.chain_calls.push((action, enabled));
+        self
+    }
+
+    /// # Description
+    /// Enable preferred busy polling on the XSK. \
+    /// `receive()` and `send()` then drive NAPI from the calling thread.
+    /// For the interrupts to stay off, `napi_defer_hard_irqs` and `gro_flush_timeout`
+    /// of the network interface should be set in sysfs as well.
+    /// # Arguments
+    /// `timeout_us` - busy polling timeout in microseconds (`SO_BUSY_POLL`) \
+    /// `budget` - packets processed per busy poll (`SO_BUSY_POLL_BUDGET`)
+    pub fn busy_poll(mut self, timeout_us: u32, budget: u32) -> Self {
+        self.busy_poll = Some(BusyPoll { timeout_us, budget });
+        self
+    }
+
+    /// # Description
+    /// Number of entries `receive()` keeps the fill ring filled up to. \
+    /// Default is the fill ring size of the `pv::Umem`. A lower value leaves more
+    /// chunks to the application at the cost of RX drops under bursts.
+    /// # Arguments
+    /// `watermark` - between 1 and the fill ring size
+    pub fn fill_watermark(mut self, watermark: usize) -> Self {
+        self.fill_watermark = Some(watermark);
+        self
+    }
+
+    /// # Description
+    /// Check the configuration before any kernel object is created.
+    /// # Returns
+    /// On success, returns `Ok(())`. \
+    /// On failure, returns `pv::Error::InvalidConfig` describing the invalid value.
+    pub fn validate(&self) -> Result<(), Error> {
+        for (name, size) in [("tx ring", self.tx_size), ("rx ring", self.rx_size)] {
+            if !size.is_power_of_two() {
+                return Err(Error::InvalidConfig(format!(
+                    "The {} size must be a power of two. (Got {})",
+                    name, size
+                )));
+            }
+        }
+
+        if self.bind_mode == BindMode::ZeroCopy && self.xdp_mode == XdpMode::Generic {
+            return Err(Error::InvalidConfig(
+                "Zero-copy bind mode requires Native XDP mode.".to_string(),
+            ));
+        }
+
+        if self.xdp_program.is_some() && self.divert.is_some() {
+            return Err(Error::InvalidConfig(
+                "An XDP program and a divert filter cannot be used together.".to_string(),
+            ));
+        }
+
+        // The XSK map of the divert program has a fixed number of entries.
+        let divert =
+            self.divert.is_some() || (self.xdp_program.is_none() && !self.chain.is_default());
+        if divert && self.queue_id >= divert::MAX_QUEUES {
+            return Err(Error::InvalidConfig(format!(
+                "The divert program serves queues below {}. (Got {})",
+                divert::MAX_QUEUES,
+                self.queue_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Attach the XDP program to `ifindex`, or `None` to let libxdp load its default one.
+    /// The libxdp default program cannot be reordered in the chain,
+    /// so a match-all divert program replaces it if chain options are set.
+    fn attach_program(&self, ifindex: u32) -> Result<Option<AttachedProgram>, Error> {
+        if let Some(program) = &self.xdp_program {
+            Ok(Some(program.attach(ifindex, self.xdp_mode, &self.chain)?))
+        } else if let Some(filter) = &self.divert {
+            Ok(Some(filter.attach(ifindex, self.xdp_mode, &self.chain)?))
+        } else if !self.chain.is_default() {
+            Ok(Some(Match::any().attach(
+                ifindex,
+                self.xdp_mode,
+                &self.chain,
+            )?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Nic {
+    /// # Description
+    /// Attaching `pv::Nic` to network interface
+    /// # Arguments
+    /// `if_name` - network interface name \
+    /// `umem` - UMEM holding the packet buffers. It can be shared with other `pv::Nic`s. \
+    /// `config` - XSK and XDP configuration. See `pv::NicConfig`.
+    /// # Returns
+    /// On success, returns `pv::Nic` bound to the network interface. \
+    /// On failure, returns `pv::Error`.
+    pub fn new(if_name: &str, umem: &Umem, config: &NicConfig) -> Result<Nic, Error> {
+        Nic::with_program(if_name, umem, config, None)
+    }
+
+    /// `Nic::new()` with the XDP program already attached to the interface, if any.
+    fn with_program(
+        if_name: &str,
+        umem: &Umem,
+        config: &NicConfig,
+        program: Option<Arc<AttachedProgram>>,
+    ) -> Result<Nic, Error> {
+        config.validate()?;
+
+        if umem.is_offline() {
+            return Err(Error::InvalidConfig(
+                "An offline UMEM cannot be used by a Nic.".to_string(),
+            ));
+        }
+
+        let umem_config = umem.config();
+        let fill_watermark = config.fill_watermark.unwrap_or(umem_config.fq_size);
+        if fill_watermark == 0 || fill_watermark > umem_config.fq_size {
+            return Err(Error::InvalidConfig(format!(
+                "The fill watermark must be between 1 and the fill ring size {}. (Got {})",
+                umem_config.fq_size, fill_watermark
+            )));
+        }
+        if umem_config.chunk_count < umem_config.fq_size + config.tx_size {
+            return Err(Error::InvalidConfig(format!(
+                "The chunk count must cover the fill ring and the tx ring. (Min = {}, Got {})",
+                umem_config.fq_size + config.tx_size,
+                umem_config.chunk_count
+            )));
+        }
+
+        let interface = find_interface(if_name)?;
+
+        umem_config.validate_mtu(read_mtu(if_name)?)?;
+
+        let counters = Arc::new(NicCounters::default());
+        let mut nic = unsafe {
+            Nic {
+                interface: interface.clone(),
+                queue_id: config.queue_id,
+                mode: config.xdp_mode,
+                bind_mode: config.bind_mode,
+                program,
+                busy_poll: config.busy_poll.is_some(),
+                tx_inflight: 0,
+                fill_watermark,
+                fq_id: umem.buffer_pool.new_fq_id(),
+                #[cfg(feature = "metrics")]
+                probe: Arc::new(metrics::NicProbe::new(
+                    if_name,
+                    config.queue_id,
+                    &counters,
+                    &umem.buffer_pool,
+                )),
+                counters,
+                backpressure: false,
+                umem: umem.clone(),
+                xsk: std::ptr::null_mut(),
+                rxq: Box::new(std::mem::zeroed()),
+                txq: Box::new(std::mem::zeroed()),
+                umem_fq: Box::new(std::mem::zeroed()),
+                umem_cq: Box::new(std::mem::zeroed()),
+            }
+        };
+
+        // On failure, dropping the Nic releases whatever open() created.
+        match Nic::open(&mut nic, config) {
+            Ok(_) => Ok(nic),
+            Err(e) => {
+                // FIXME: Print here is fine. But segfault happened when printing in the caller.
+                eprintln!("Failed to open NIC: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    fn open(&mut self, config: &NicConfig) -> Result<(), Error> {
+        let mut xsk_cfg: xsk_socket_config = xsk_socket_config {
+            rx_size: config.rx_size.try_into().unwrap(),
+            tx_size: config.tx_size.try_into().unwrap(),
+            __bindgen_anon_1: xsk_socket_config__bindgen_ty_1 { libxdp_flags: 0 },
+            xdp_flags: match config.xdp_mode {
+                XdpMode::Generic => XDP_FLAGS_SKB_MODE,
+                _ => XDP_FLAGS_DRV_MODE,
+            },
+            bind_flags: match config.bind_mode {
+                BindMode::Auto => XDP_USE_NEED_WAKEUP,
+                BindMode::ZeroCopy => XDP_USE_NEED_WAKEUP | XDP_ZEROCOPY,
+                BindMode::Copy => XDP_USE_NEED_WAKEUP | XDP_COPY,
+            } as u16,
+        };
+        let if_name = CString::new(self.interface.name.clone()).unwrap();
+        let if_ptr = if_name.as_ptr() as *const c_char;
+
+        /*
+         * With a user-supplied or divert program, libxdp must not load its own.
+         * A Nic attaches its own program into the libxdp dispatcher,
+         * so dropping it removes only its program from the chain.
+         * The Nics of a NicGroup share one program, whose XSKMAP holds every queue,
+         * so that a program redirecting into any queue reaches its XSK.
+         */
+        if self.program.is_none() {
+            self.program = config.attach_program(self.interface.index)?.map(Arc::new);
+        }
+        if self.program.is_some() {
+            xsk_cfg.__bindgen_anon_1.libxdp_flags = XSK_LIBXDP_FLAGS__INHIBIT_PROG_LOAD;
+        }
+
+        let umem = self.umem.clone();
+        let mut pool = umem.pool.lock().unwrap();
+
+        let ret: c_int = unsafe {
+            xsk_socket__create_shared(
+                &mut self.xsk,
+                if_ptr,
+                self.queue_id,
+                pool.umem,
+                &mut *self.rxq,
+                &mut *self.txq,
+                &mut *self.umem_fq,
+                &mut *self.umem_cq,
+                &xsk_cfg,
+            )
+        };
+
+        // Mode being tried, for the error message. Read back once the XSK exists.
+        self.mode = match (&self.program, config.xdp_mode) {
+            (Some(program), _) => program.mode(),
+            (None, XdpMode::Generic) => XdpMode::Generic,
+            (None, _) => XdpMode::Native,
+        };
+
+        // Zero-copy is only available in Native mode, so there is nothing to fall back to.
+        // A user-supplied program has already fallen back while being attached.
+        let fallback = config.xdp_mode == XdpMode::Auto
+            && config.bind_mode != BindMode::ZeroCopy
+            && self.program.is_none();
+
+        if ret != 0 {
+            self.xsk = std::ptr::null_mut();
+        }
+
+        // EOPNOTSUPP in zero-copy bind mode means the driver does not support zero-copy.
+        if ret == -libc::EOPNOTSUPP && config.bind_mode == BindMode::ZeroCopy {
+            return Err(Error::ZeroCopyUnsupported {
+                interface: self.interface.name.clone(),
+                queue_id: self.queue_id,
+            });
+        }
+
+        if ret != 0 && !fallback {
+            return Err(self.socket_create_error(ret));
+        }
+
+        if ret != 0 {
+            if pool.refcount == 0 {
+                // Pool Full-Fallback
+                pool.re_create()?;
+            } else {
+                // Pool Semi-Fallback
+                thread::sleep(Duration::from_millis(100));
+            }
+
+            xsk_cfg.xdp_flags = XDP_FLAGS_SKB_MODE;
+            let ret: c_int = unsafe {
+                xsk_socket__create_shared(
+                    &mut self.xsk,
+                    if_ptr,
+                    self.queue_id,
+                    pool.umem,
+                    &mut *self.rxq,
+                    &mut *self.txq,
+                    &mut *self.umem_fq,
+                    &mut *self.umem_cq,
+                    &xsk_cfg,
+                )
+            };
+
+            self.mode = XdpMode::Generic;
+            if ret != 0 {
+                self.xsk = std::ptr::null_mut();
+                return Err(self.socket_create_error(ret));
+            }
+        }
+
+        // Counted as soon as the XSK exists, as Drop deletes it from here on.
+        pool.refcount += 1;
+
+        if let Some(program) = &self.program {
+            program.register(self.xsk)?;
+        }
+
+        // libxdp loads its default program itself, so ask the kernel which mode it got.
+        self.mode = match &self.program {
+            Some(program) => program.mode(),
+            None => read_xdp_mode(&self.interface)?,
+        };
+        self.bind_mode = read_bind_mode(self.xsk)?;
+
+        if let Some(busy_poll) = config.busy_poll {
+            let fd = unsafe { xsk_socket__fd(self.xsk) };
+            for (name, option, value) in [
+                ("SO_PREFER_BUSY_POLL", SO_PREFER_BUSY_POLL, 1),
+                ("SO_BUSY_POLL", SO_BUSY_POLL, busy_poll.timeout_us),
+                ("SO_BUSY_POLL_BUDGET", SO_BUSY_POLL_BUDGET, busy_poll.budget),
+            ] {
+                set_socket_option(fd, option, value as c_int)
+                    .map_err(|source| Error::SocketOption { name, source })?;
+            }
+        }
+
+        /*
+         * After calling xsk_umem__create(), the fill_q and comp_q of the UMEM are initialized.
+         * libxdp copies them into the rings of the first XSK through xsk_socket__create_shared(),
+         * and creates new ones for the later XSKs.
+         * Both Pool and Nic keep their rings boxed, so the pointers libxdp keeps stay valid
+         * when they are moved, and libxdp can unmap the rings when the XSK is deleted.
+         */
+
+        let (refilled, _) =
+            pool.buffer_pool
+                .refill_fq(&mut self.umem_fq, self.fq_id, self.fill_watermark);
+        if refilled == 0 {
+            return Err(Error::PoolEmpty);
+        }
+
+        #[cfg(feature = "metrics")]
+        self.probe.open(self);
+
+        Ok(())
+    }
+
+    fn socket_create_error(&self, ret: c_int) -> Error {
+        Error::SocketCreate {
+            interface: self.interface.name.clone(),
+            queue_id: self.queue_id,
+            mode: self.mode,
+            source: Error::from_errno(ret),
+        }
+    }
+
+    /// # Description
+    /// RX queue of the network interface this `pv::Nic` is bound to.
+    pub fn queue_id(&self) -> u32 {
+        self.queue_id
+    }
+
+    /// # Description
+    /// XDP mode actually in use, either `XdpMode::Native` or `XdpMode::Generic`.
+    pub fn mode(&self) -> XdpMode {
+        self.mode
+    }
+
+    /// # Description
+    /// XSK bind mode actually in use, either `BindMode::ZeroCopy` or `BindMode::Copy`.
+    pub fn bind_mode(&self) -> BindMode {
+        self.bind_mode
+    }
+
+    /// # Description
+    /// UMEM this `pv::Nic` uses.
+    pub fn umem(&self) -> &Umem {
+        &self.umem
+    }
+
+    /// # Description
+    /// Read the statistics the kernel keeps for the XSK
+    /// # Returns
+    /// On success, returns `pv::XskStats`. \
+    /// On failure, returns `pv::Error::SocketOption`.
+    pub fn xsk_stats(&self) -> Result<XskStats, Error> {
+        read_xsk_stats(self.xsk)
+    }
+
+    /// # Description
+    /// Allocate packet from the UMEM of this `pv::Nic`
+    /// # Returns
+    /// On success, returns `pv::Packet` with empty payload. \
+    /// On failure, returns `None`.
+    pub fn alloc_packet(&self) -> Option<Packet> {
+        let packet = self.umem.alloc_packet();
+        if packet.is_none() {
+            NicCounters::add(&self.counters.alloc_failures, 1);
+        }
+
+        packet
+    }
+
+    /// # Description
+    /// Allocate `len` packets from the UMEM of this `pv::Nic` at once. See `pv::Umem::alloc_packets()`.
+    /// # Returns
+    /// On success, returns `len` `pv::Packet`s with empty payload. \
+    /// If fewer than `len` chunks are free, returns `None` and allocates nothing.
+    pub fn alloc_packets(&self, len: usize) -> Option<Vec<Packet>> {
+        let packets = self.umem.alloc_packets(len);
+        if packets.is_none() {
+            NicCounters::add(&self.counters.alloc_failures, 1);
+        }
+
+        packets
+    }
+
+    /// # Description
+    /// Allocate as many packets as possible, up to `len`, from the UMEM of this `pv::Nic` at once
+    /// # Arguments
+    /// `packets` - Vector to append the allocated packets to \
+    /// `len` - Maximum number of packets to allocate
+    /// # Returns
+    /// Number of packets appended to `packets`
+    pub fn alloc_packets_into(&self, packets: &mut Vec<Packet>, len: usize) -> usize {
+        let count = self.umem.alloc_packets_into(packets, len);
+        if count < len {
+            NicCounters::add(&self.counters.alloc_failures, 1);
+        }
+
+        count
+    }
+
+    /// # Description
+    /// Send packets \
+    /// **\*Sent packets are removed from the vector.**
+    /// # Arguments
+    /// `packets` - Packets to send
+    /// # Returns
+    /// Number of packets sent
+    pub fn send(&mut self, packets: &mut Vec<Packet>) -> usize {
+        self.complete_tx();
+        let buffer_pool = &self.umem.buffer_pool;
+        let sent_count = buffer_pool.send(
+            packets,
+            &self.xsk,
+            &mut self.txq,
+            self.busy_poll,
+            &self.counters,
+        );
+        self.tx_inflight += sent_count;
+
+        sent_count
+    }
+
+    /// # Description
+    /// Number of sent packets whose chunks are still owned by the kernel. \
+    /// They return to the UMEM when the kernel reports their completion,
+    /// which is checked in `send()` and `receive()`.
+    pub fn tx_in_flight(&self) -> usize {
+        self.tx_inflight
+    }
+
+    /// Return the chunks of completed TX packets to the pool.
+    fn complete_tx(&mut self) {
+        let buffer_pool = &self.umem.buffer_pool;
+        let completed = buffer_pool.release(&mut self.umem_cq, buffer_pool.cq_size);
+        self.tx_inflight -= completed as usize;
+    }
+
+    /// Wait a while for the in-flight TX packets to complete, before the XSK is closed.
+    fn drain_tx(&mut self) {
+        for _ in 0..TX_DRAIN_RETRIES {
+            self.complete_tx();
+            if self.tx_inflight == 0 {
+                return;
+            }
+            wakeup_tx(self.xsk);
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        eprintln!(
+            "{} TX chunks did not complete before closing the XSK",
+            self.tx_inflight
+        );
+    }
+
+    /// # Description
+    /// Receive packets, waiting up to `timeout` if none are ready
+    /// # Arguments
+    /// `len` - Number of packets to receive \
+    /// `timeout` - Maximum time to wait for packets
+    /// # Returns
+    /// Received packets. Empty if `timeout` expired or the wait was interrupted.
+    pub fn receive_timeout(&mut self, len: usize, timeout: Duration) -> Vec<Packet> {
+        let packets = self.receive(len);
+        if !packets.is_empty() {
+            return packets;
+        }
+
+        let mut pfd = libc::pollfd {
+            fd: self.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ts = libc::timespec {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_nsec: timeout.subsec_nanos() as libc::c_long,
+        };
+        let ret = unsafe { libc::ppoll(&mut pfd, 1, &ts, std::ptr::null()) };
+        if ret <= 0 {
+            return packets;
+        }
+
+        self.receive(len)
+    }
+
+    /// # Description
+    /// Receive packets
+    /// # Arguments
+    /// `len` - Number of packets to receive
+    /// # Returns
+    /// Received packets
+    pub fn receive(&mut self, len: usize) -> Vec<Packet> {
+        self.complete_tx();
+        let packets = self
+            .umem
+            .buffer_pool
+            .recv(len, &mut self.rxq, &self.counters);
+
+        // Refill even if nothing was received, to recover from an exhausted pool.
+        let (_, shortfall) =
+            self.umem
+                .buffer_pool
+                .refill_fq(&mut self.umem_fq, self.fq_id, self.fill_watermark);
+        NicCounters::add(&self.counters.fill_shortfall, shortfall as u64);
+        self.backpressure = shortfall > 0;
+
+        /*
+         * XSK manages interrupts through xsk_ring_prod__needs_wakup().
+         *
+         * If Packetvisor is assigned to core indexes [0] or [1], the Rx interrupt does not work properly.
+         * This significantly degrades Packetvisor performance.
+         * To resolve this issue, the interrupt is woken up whenever Recv() is called.
+         * In busy polling mode, nothing arrives unless the application drives NAPI.
+         */
+        if self.busy_poll || unsafe { xsk_ring_prod__needs_wakeup(&*self.umem_fq) } != 0 {
+            wakeup_rx(self.xsk);
+            NicCounters::add(&self.counters.rx_wakeups, 1);
+        }
+
+        packets
+    }
+
+    /// # Description
+    /// Whether the last `receive()` could not refill the fill ring up to the watermark,
+    /// because every UMEM chunk is held by the application or in flight. \
+    /// Drop or send held packets while this is `true`, or RX drops follow.
+    pub fn backpressure(&self) -> bool {
+        self.backpressure
+    }
+
+    /// # Description
+    /// Total number of chunks the fill ring lacked after refilling.
+    pub fn fill_shortfall(&self) -> u64 {
+        self.counters.fill_shortfall()
+    }
+
+    /// # Description
+    /// Counters of this `pv::Nic`. The handle stays valid after the `pv::Nic` is dropped.
+    pub fn counters(&self) -> Arc<NicCounters> {
+        self.counters.clone()
+    }
+}
+
+/// File descriptor of the XSK. Readable when packets are received,
+/// so it can be registered in an event loop such as `epoll`.
+impl AsRawFd for Nic {
+    fn as_raw_fd(&self) -> RawFd {
+        unsafe { xsk_socket__fd(self.xsk) }
+    }
+}
+
+impl AsFd for Nic {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) }
+    }
+}
+
+impl PacketIo for Nic {
+    fn interface(&self) -> &NetworkInterface {
+        &self.interface
+    }
+
+    fn alloc_packet(&self) -> Option<Packet> {
+        Nic::alloc_packet(self)
+    }
+
+    fn alloc_packets(&self, len: usize) -> Option<Vec<Packet>> {
+        Nic::alloc_packets(self, len)
+    }
+
+    fn alloc_packets_into(&self, packets: &mut Vec<Packet>, len: usize) -> usize {
+        Nic::alloc_packets_into(self, packets, len)
+    }
+
+    fn receive(&mut self, len: usize) -> Vec<Packet> {
+        Nic::receive(self, len)
+    }
+
+    fn receive_timeout(&mut self, len: usize, timeout: Duration) -> Vec<Packet> {
+        Nic::receive_timeout(self, len, timeout)
+    }
+
+    fn send(&mut self, packets: &mut Vec<Packet>) -> usize {
+        Nic::send(self, packets)
+    }
+
+    fn counters(&self) -> Arc<NicCounters> {
+        Nic::counters(self)
+    }
+}
+
+impl NicGroup {
+    /// # Description
+    /// Attaching `pv::Nic` to every RX queue of network interface. \
+    /// The number of queues is read through the ethtool ioctl.
+    /// # Arguments
+    /// `if_name` - network interface name \
+    /// `umem` - UMEM shared by every queue \
+    /// `config` - XSK and XDP configuration. `queue_id` is ignored. \
+    /// An XDP program is attached once and shared by every queue.
+    /// # Returns
+    /// On success, returns `pv::NicGroup` holding a `pv::Nic` per queue. \
+    /// On failure, returns `pv::Error`.
+    pub fn new(if_name: &str, umem: &Umem, config: &NicConfig) -> Result<NicGroup, Error> {
+        let queue_count = read_queue_count(if_name)?;
+        // Every queue is checked by checking the last one.
+        config
+            .clone()
+            .queue_id(queue_count.saturating_sub(1) as u32)
+            .validate()?;
+
+        let umem_config = umem.config();
+        let required = queue_count * (umem_config.fq_size + config.tx_size);
+        if umem_config.chunk_count < required {
+            return Err(Error::InvalidConfig(format!(
+                "The chunk count must cover the fill ring and the tx ring of {} queues. (Min = {}, Got {})",
+                queue_count, required, umem_config.chunk_count
+            )));
+        }
+
+        // Detached once the last Nic is dropped, also if creating one of them fails.
+        let interface = find_interface(if_name)?;
+        let program = config.attach_program(interface.index)?.map(Arc::new);
+
+        let nics = (0..queue_count as u32)
+            .map(|queue_id| {
+                let config = config.clone().queue_id(queue_id);
+                Nic::with_program(if_name, umem, &config, program.clone())
+            })
+            .collect::<Result<Vec<Nic>, Error>>()?;
+
+        Ok(NicGroup { nics })
+    }
+
+    /// Number of queues in the group.
+    pub fn len(&self) -> usize {
+        self.nics.len()
+    }
+
+    /// Returns `true` if the group has no queue.
+    pub fn is_empty(&self) -> bool {
+        self.nics.is_empty()
+    }
+
+    /// `pv::Nic`s ordered by queue id.
+    pub fn nics(&self) -> &[Nic] {
+        &self.nics
+    }
+
+    /// Mutable `pv::Nic`s ordered by queue id.
+    pub fn nics_mut(&mut self) -> &mut [Nic] {
+        &mut self.nics
+    }
+}
+
+impl From<Nic> for NicGroup {
+    fn from(nic: Nic) -> Self {
+        NicGroup { nics: vec![nic] }
+    }
+}
+
+impl NicCounters {
+    /// Received packets.
+    pub fn rx_packets(&self) -> u64 {
+        self.rx_packets.load(Ordering::Relaxed)
+    }
+
+    /// Received bytes, without the headroom.
+    pub fn rx_bytes(&self) -> u64 {
+        self.rx_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Packets put on the TX ring.
+    pub fn tx_packets(&self) -> u64 {
+        self.tx_packets.load(Ordering::Relaxed)
+    }
+
+    /// Bytes put on the TX ring.
+    pub fn tx_bytes(&self) -> u64 {
+        self.tx_bytes.load(Ordering::Relaxed)
+    }
+
+    /// `send()` calls that found fewer free TX ring slots than packets. \
+    /// If this grows, consider a larger TX ring or smaller batches.
+    pub fn tx_partial(&self) -> u64 {
+        self.tx_partial.load(Ordering::Relaxed)
+    }
+
+    /// Packet allocations through the `pv::Nic` that got fewer chunks than requested.
+    pub fn alloc_failures(&self) -> u64 {
+        self.alloc_failures.load(Ordering::Relaxed)
+    }
+
+    /// Chunks the fill ring lacked after refilling. See `Nic::backpressure()`.
+    pub fn fill_shortfall(&self) -> u64 {
+        self.fill_shortfall.load(Ordering::Relaxed)
+    }
+
+    /// Syscalls made to wake up the kernel for RX.
+    pub fn rx_wakeups(&self) -> u64 {
+        self.rx_wakeups.load(Ordering::Relaxed)
+    }
+
+    /// Syscalls made to wake up the kernel for TX.
+    pub fn tx_wakeups(&self) -> u64 {
+        self.tx_wakeups.load(Ordering::Relaxed)
+    }
+
+    // Only the owning Nic writes, so a load and store is enough.
+    fn add(counter: &AtomicU64, value: u64) {
+        if value > 0 {
+            counter.store(
+                counter.load(Ordering::Relaxed).wrapping_add(value),
+                Ordering::Relaxed,
+            );
+        }
+    }
+}
+
+impl XskStats {
+    /// # Description
+    /// Difference from the earlier statistics `earlier`
+    /// # Arguments
+    /// `earlier` - statistics read before `self` from the same `pv::Nic`
+    pub fn delta(&self, earlier: &XskStats) -> XskStats {
+        XskStats {
+            rx_dropped: self.rx_dropped.wrapping_sub(earlier.rx_dropped),
+            rx_invalid_descs: self.rx_invalid_descs.wrapping_sub(earlier.rx_invalid_descs),
+            tx_invalid_descs: self.tx_invalid_descs.wrapping_sub(earlier.tx_invalid_descs),
+            rx_ring_full: self.rx_ring_full.wrapping_sub(earlier.rx_ring_full),
+            rx_fill_ring_empty_descs: self
+                .rx_fill_ring_empty_descs
+                .wrapping_sub(earlier.rx_fill_ring_empty_descs),
+            tx_ring_empty_descs: self
+                .tx_ring_empty_descs
+                .wrapping_sub(earlier.tx_ring_empty_descs),
+        }
+    }
+}
+
+impl Packet {
+    fn new(chunk_pool: &Arc<BufferPool>) -> Packet {
+        Packet {
+            start: DEFAULT_HEADROOM,
+            end: DEFAULT_HEADROOM,
+            buffer_size: 0,
             buffer: std::ptr::null_mut(),
             private: std::ptr::null_mut(),
             buffer_pool: chunk_pool.clone(),
@@ -737,26 +1897,42 @@ impl Packet {
 
     /// # Description
     /// Replace payload with new data. \
-    /// Data can be memmoved if needed.
+    /// The headroom is kept if `new_data` fits after it. Otherwise it is used for the data too.
     /// # Arguments
     /// `new_data` - new packet payload
     /// # Returns
-    /// On success, returns `None` and payload of `pv::Packet` is replaced with `new_data`. \
-    /// On failure, returns an error string.
-    pub fn replace_data(&mut self, new_data: &[u8]) -> Result<(), String> {
+    /// On success, returns `Ok(())` and payload of `pv::Packet` is replaced with `new_data`. \
+    /// On failure, returns `pv::Error::BufferTooSmall`.
+    pub fn replace_data(&mut self, new_data: &[u8]) -> Result<(), Error> {
         if new_data.len() <= self.buffer_size {
+            let start = match new_data.len() <= self.buffer_size - DEFAULT_HEADROOM {
+                true => DEFAULT_HEADROOM,
+                false => 0,
+            };
             unsafe {
                 // replace data
-                copy(new_data.as_ptr(), self.buffer.offset(0), new_data.len());
-                self.start = 0;
-                self.end = new_data.len();
+                copy(new_data.as_ptr(), self.buffer.add(start), new_data.len());
+                self.start = start;
+                self.end = start + new_data.len();
 
                 Ok(())
             }
         } else {
-            Err(String::from(
-                "Data size is over than buffer size of packet.",
-            ))
+            Err(Error::BufferTooSmall {
+                requested: new_data.len(),
+                available: self.buffer_size,
+            })
+        }
+    }
+
+    /// # Description
+    /// Get payload
+    pub fn get_buffer(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self.buffer.offset(self.start.try_into().unwrap()),
+                self.end - self.start,
+            )
         }
     }
 
@@ -776,36 +1952,131 @@ impl Packet {
     /// # Arguments
     /// `new_size` - new packet payload size
     /// # Returns
-    /// On success, return None and payload size of `pv::Packet` is replaced with `new_size`. \
-    /// On failure, returns an error string.
-    pub fn resize(&mut self, new_size: usize) -> Result<(), String> {
+    /// On success, returns `Ok(())` and payload size of `pv::Packet` is replaced with `new_size`. \
+    /// On failure, returns `pv::Error::BufferTooSmall`.
+    pub fn resize(&mut self, new_size: usize) -> Result<(), Error> {
         if new_size > self.buffer_size {
-            return Err(format!(
-                "The requested size is to large. (Max = {})",
-                self.buffer_size
-            ));
+            return Err(Error::BufferTooSmall {
+                requested: new_size,
+                available: self.buffer_size,
+            });
         }
 
-        let temp_end = self.end;
-
         if new_size > self.buffer_size - self.start {
-            // Need to move data
+            // Move the payload back just enough, keeping the rest of the headroom.
+            let start = self.buffer_size - new_size;
             unsafe {
                 copy(
-                    self.buffer.offset(self.start.try_into().unwrap()),
-                    self.buffer,
-                    temp_end,
+                    self.buffer.add(self.start),
+                    self.buffer.add(start),
+                    self.end - self.start,
                 );
             }
-            self.start = 0;
-            self.end = new_size;
-            return Ok(());
+            self.start = start;
         }
 
         self.end = self.start + new_size;
         Ok(())
     }
 
+    /// # Description
+    /// Prepend `len` bytes to the payload using the headroom. No data is moved.
+    /// # Arguments
+    /// `len` - number of bytes to prepend
+    /// # Returns
+    /// On success, returns the prepended bytes to fill in, such as an outer header. \
+    /// On failure, returns `pv::Error::BufferTooSmall` and the payload is not changed.
+    pub fn push_front(&mut self, len: usize) -> Result<&mut [u8], Error> {
+        if len > self.start {
+            return Err(Error::BufferTooSmall {
+                requested: len,
+                available: self.start,
+            });
+        }
+
+        self.start -= len;
+        Ok(self.slice_mut(self.start, len))
+    }
+
+    /// # Description
+    /// Remove `len` bytes from the start of the payload. They become headroom.
+    /// # Arguments
+    /// `len` - number of bytes to remove
+    /// # Returns
+    /// On success, returns `Ok(())`. \
+    /// On failure, returns `pv::Error::PayloadTooShort` and the payload is not changed.
+    pub fn pull_front(&mut self, len: usize) -> Result<(), Error> {
+        if len > self.end - self.start {
+            return Err(Error::PayloadTooShort {
+                requested: len,
+                available: self.end - self.start,
+            });
+        }
+
+        self.start += len;
+        Ok(())
+    }
+
+    /// # Description
+    /// Append `len` bytes to the payload using the tailroom.
+    /// # Arguments
+    /// `len` - number of bytes to append
+    /// # Returns
+    /// On success, returns the appended bytes to fill in, such as a trailer. \
+    /// On failure, returns `pv::Error::BufferTooSmall` and the payload is not changed.
+    pub fn push_back(&mut self, len: usize) -> Result<&mut [u8], Error> {
+        if len > self.buffer_size - self.end {
+            return Err(Error::BufferTooSmall {
+                requested: len,
+                available: self.buffer_size - self.end,
+            });
+        }
+
+        self.end += len;
+        Ok(self.slice_mut(self.end - len, len))
+    }
+
+    /// # Description
+    /// Remove `len` bytes from the end of the payload.
+    /// # Arguments
+    /// `len` - number of bytes to remove
+    /// # Returns
+    /// On success, returns `Ok(())`. \
+    /// On failure, returns `pv::Error::PayloadTooShort` and the payload is not changed.
+    pub fn trim_back(&mut self, len: usize) -> Result<(), Error> {
+        if len > self.end - self.start {
+            return Err(Error::PayloadTooShort {
+                requested: len,
+                available: self.end - self.start,
+            });
+        }
+
+        self.end -= len;
+        Ok(())
+    }
+
+    /// # Description
+    /// Free space before the payload.
+    pub fn headroom(&self) -> usize {
+        self.start
+    }
+
+    /// # Description
+    /// Free space after the payload.
+    pub fn tailroom(&self) -> usize {
+        self.buffer_size - self.end
+    }
+
+    fn slice_mut(&mut self, offset: usize, len: usize) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.buffer.add(offset), len) }
+    }
+
+    /// Give the chunk to the kernel for TX without returning it to the pool.
+    fn into_inflight(self) {
+        let mut packet = ManuallyDrop::new(self);
+        unsafe { std::ptr::drop_in_place(&mut packet.buffer_pool) };
+    }
+
     /// # Description
     /// Dump packet payload as hex. Debugging purpose
     #[allow(dead_code)]
@@ -827,9 +2098,9 @@ impl Packet {
                 count += 1;
                 if count == length {
                     break;
-                } else if count % 8 == 0 {
+                } else if count.is_multiple_of(8) {
                     print!(" ");
-                    if count % 16 == 0 {
+                    if count.is_multiple_of(16) {
                         println!();
                     }
                 }
@@ -839,11 +2110,30 @@ impl Packet {
     }
 }
 
+/*
+ * The raw pointers are owned by each object and never shared without the locks above,
+ * so RX, processing and TX can run on different threads.
+ */
+unsafe impl Send for BufferPool {}
+unsafe impl Sync for BufferPool {}
+unsafe impl Send for Pool {}
+unsafe impl Send for Nic {}
+unsafe impl Send for Packet {}
+
 /********************************************************************
  *
  * Drop
  *
  *******************************************************************/
+impl Drop for BufferPool {
+    fn drop(&mut self) {
+        // Last owner: the UMEM is deleted and no packet refers to the buffer.
+        unsafe {
+            libc::munmap(self.buffer, self.buffer_size);
+        }
+    }
+}
+
 impl Drop for Pool {
     fn drop(&mut self) {
         // Free UMEM
@@ -857,18 +2147,32 @@ impl Drop for Pool {
 impl Drop for Nic {
     // move ownership of nic
     fn drop(&mut self) {
+        // open() failed before the XSK was created.
+        if self.xsk.is_null() {
+            return;
+        }
+
+        #[cfg(feature = "metrics")]
+        self.probe.close();
+
+        // Stop redirecting packets to this XSK.
+        self.program = None;
+        self.drain_tx();
+
         // xsk delete
         unsafe {
             xsk_socket__delete(self.xsk);
-            let pool = Pool::instance();
-            (*pool).refcount -= 1;
         };
+
+        // The kernel is done with the FQ, so its chunks can be handed out again.
+        self.umem.buffer_pool.reclaim(self.fq_id);
+        self.umem.pool.lock().unwrap().refcount -= 1;
     }
 }
 
 impl Drop for Packet {
     fn drop(&mut self) {
-        self.buffer_pool.borrow_mut().free_addr(self.private as u64);
+        self.buffer_pool.free_addr(self.private as u64);
     }
 }
 
@@ -877,15 +2181,474 @@ impl Drop for Packet {
  * Other functions
  *
  *******************************************************************/
-fn alloc_zeroed_layout<T: 'static>() -> Result<*mut u8, String> {
-    let ptr;
+/// Map the UMEM buffer as configured, falling back to regular pages if allowed.
+/// # Returns
+/// (Buffer address, Mapped length, Page size in use)
+fn map_umem(config: &UmemConfig) -> Result<(*mut c_void, usize, PageSize), Error> {
+    let size = config.chunk_size * config.chunk_count;
+    let (address, buffer_size, page_size) = match map_buffer(size, config.page_size, config.memfd) {
+        Ok((address, buffer_size)) => (address, buffer_size, config.page_size),
+        Err(e) if config.page_size != PageSize::Default && config.hugepage_fallback => {
+            eprintln!(
+                "Failed to allocate {:?} pages for UMEM, falling back to regular pages: {}",
+                config.page_size, e
+            );
+            let (address, buffer_size) =
+                map_buffer(size, PageSize::Default, config.memfd).map_err(Error::UmemCreate)?;
+            (address, buffer_size, PageSize::Default)
+        }
+        Err(e) => return Err(Error::UmemCreate(e)),
+    };
+
+    // Bind before the first touch, so that no page is placed on another node.
+    if let Some(node) = config.numa_node {
+        if let Err(e) = bind_numa_node(address, buffer_size, node) {
+            unsafe {
+                libc::munmap(address, buffer_size);
+            }
+            return Err(Error::UmemCreate(e));
+        }
+    }
+
+    Ok((address, buffer_size, page_size))
+}
+
+/// mmap `size` bytes rounded up to `page_size`, anonymous or memfd backed.
+fn map_buffer(
+    size: usize,
+    page_size: PageSize,
+    memfd: bool,
+) -> Result<(*mut c_void, usize), std::io::Error> {
+    let (page_bytes, map_flags, mfd_flags) = match page_size {
+        PageSize::Default => (unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize, 0, 0),
+        PageSize::Huge2M => (
+            2 << 20,
+            libc::MAP_HUGETLB | libc::MAP_HUGE_2MB,
+            libc::MFD_HUGETLB | libc::MFD_HUGE_2MB,
+        ),
+        PageSize::Huge1G => (
+            1 << 30,
+            libc::MAP_HUGETLB | libc::MAP_HUGE_1GB,
+            libc::MFD_HUGETLB | libc::MFD_HUGE_1GB,
+        ),
+    };
+    let size = size.div_ceil(page_bytes) * page_bytes;
+
+    let mut fd = -1;
+    let mut flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | map_flags;
+    if memfd {
+        let name = CString::new("pv_umem").unwrap();
+        fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC | mfd_flags) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if unsafe { libc::ftruncate(fd, size as libc::off_t) } != 0 {
+            let e = std::io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(e);
+        }
+        flags = libc::MAP_SHARED;
+    }
+
+    let address = unsafe {
+        libc::mmap(
+            std::ptr::null_mut::<libc::c_void>(),
+            size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            flags,
+            fd,
+            0, // offset
+        )
+    };
+    let e = std::io::Error::last_os_error();
+
+    // The mapping keeps the memfd alive.
+    if fd >= 0 {
+        unsafe { libc::close(fd) };
+    }
+
+    if address == libc::MAP_FAILED {
+        return Err(e);
+    }
+
+    Ok((address, size))
+}
+
+fn bind_numa_node(address: *mut c_void, size: usize, node: usize) -> Result<(), std::io::Error> {
+    let mut nodemask = [0 as libc::c_ulong; MAX_NUMA_NODES / libc::c_ulong::BITS as usize];
+    nodemask[node / libc::c_ulong::BITS as usize] |= 1 << (node % libc::c_ulong::BITS as usize);
+
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_mbind,
+            address,
+            size,
+            MPOL_BIND,
+            nodemask.as_ptr(),
+            MAX_NUMA_NODES + 1, // the kernel uses maxnode - 1 bits.
+            0,
+        )
+    };
+
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+fn wakeup_rx(xsk: *mut xsk_socket) {
     unsafe {
-        let layout = Layout::new::<T>();
-        ptr = alloc_zeroed(layout);
+        libc::recvfrom(
+            xsk_socket__fd(xsk),
+            std::ptr::null_mut::<libc::c_void>(),
+            0 as libc::size_t,
+            libc::MSG_DONTWAIT,
+            std::ptr::null_mut::<libc::sockaddr>(),
+            std::ptr::null_mut::<u32>(),
+        );
+    }
+}
+
+fn wakeup_tx(xsk: *mut xsk_socket) {
+    unsafe {
+        libc::sendto(
+            xsk_socket__fd(xsk),
+            std::ptr::null::<libc::c_void>(),
+            0 as libc::size_t,
+            libc::MSG_DONTWAIT,
+            std::ptr::null::<libc::sockaddr>(),
+            0 as libc::socklen_t,
+        );
+    }
+}
+
+fn set_socket_option(fd: c_int, option: c_int, value: c_int) -> Result<(), std::io::Error> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            option,
+            &value as *const c_int as *const c_void,
+            std::mem::size_of::<c_int>() as libc::socklen_t,
+        )
+    };
+
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+fn find_interface(if_name: &str) -> Result<NetworkInterface, Error> {
+    interfaces()
+        .into_iter()
+        .find(|elem| elem.name.as_str() == if_name)
+        .ok_or_else(|| Error::InterfaceNotFound(if_name.to_string()))
+}
+
+fn read_xdp_mode(interface: &NetworkInterface) -> Result<XdpMode, Error> {
+    let mp = unsafe { xdp_multiprog__get_from_ifindex(interface.index as c_int) };
+    let ret = unsafe { libxdp_get_error(mp as *const c_void) } as c_int;
+    if ret != 0 || mp.is_null() {
+        return Err(Error::XdpProgram {
+            context: format!("Failed to read the XDP mode of {}", interface.name),
+            source: Error::from_errno(if ret != 0 { ret } else { libc::ENOENT }),
+        });
+    }
+
+    let mode = unsafe { xdp_multiprog__attach_mode(mp) };
+    unsafe { xdp_multiprog__close(mp) };
+
+    if mode == xdp_attach_mode_XDP_MODE_SKB {
+        Ok(XdpMode::Generic)
+    } else if mode == xdp_attach_mode_XDP_MODE_NATIVE || mode == xdp_attach_mode_XDP_MODE_HW {
+        Ok(XdpMode::Native)
+    } else {
+        Err(Error::XdpProgram {
+            context: format!("No XDP program is attached to {}", interface.name),
+            source: Error::from_errno(libc::ENOENT),
+        })
+    }
+}
+
+fn read_bind_mode(xsk: *mut xsk_socket) -> Result<BindMode, Error> {
+    let mut options = xdp_options { flags: 0 };
+    let mut optlen = std::mem::size_of::<xdp_options>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            xsk_socket__fd(xsk),
+            SOL_XDP,
+            XDP_OPTIONS as c_int,
+            &mut options as *mut xdp_options as *mut c_void,
+            &mut optlen,
+        )
+    };
+
+    if ret != 0 {
+        return Err(Error::SocketOption {
+            name: "XDP_OPTIONS",
+            source: std::io::Error::last_os_error(),
+        });
     }
-    if ptr.is_null() {
-        Err("failed to allocate memory".to_string())
+
+    if options.flags & XDP_OPTIONS_ZEROCOPY != 0 {
+        Ok(BindMode::ZeroCopy)
     } else {
-        Ok(ptr)
+        Ok(BindMode::Copy)
+    }
+}
+
+fn read_xsk_stats(xsk: *mut xsk_socket) -> Result<XskStats, Error> {
+    // Kernels before 5.9 fill only the first three fields, the rest stay zero.
+    let mut stats: xdp_statistics = unsafe { std::mem::zeroed() };
+    let mut optlen = std::mem::size_of::<xdp_statistics>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            xsk_socket__fd(xsk),
+            SOL_XDP,
+            XDP_STATISTICS as c_int,
+            &mut stats as *mut xdp_statistics as *mut c_void,
+            &mut optlen,
+        )
+    };
+
+    if ret != 0 {
+        return Err(Error::SocketOption {
+            name: "XDP_STATISTICS",
+            source: std::io::Error::last_os_error(),
+        });
+    }
+
+    Ok(XskStats {
+        rx_dropped: stats.rx_dropped,
+        rx_invalid_descs: stats.rx_invalid_descs,
+        tx_invalid_descs: stats.tx_invalid_descs,
+        rx_ring_full: stats.rx_ring_full,
+        rx_fill_ring_empty_descs: stats.rx_fill_ring_empty_descs,
+        tx_ring_empty_descs: stats.tx_ring_empty_descs,
+    })
+}
+
+fn read_queue_count(if_name: &str) -> Result<usize, Error> {
+    if if_name.len() >= libc::IF_NAMESIZE {
+        return Err(Error::InterfaceNotFound(if_name.to_string()));
+    }
+
+    let mut channels = EthtoolChannels {
+        cmd: ETHTOOL_GCHANNELS,
+        ..Default::default()
+    };
+    let mut ifr = IfReq {
+        ifr_name: [0; libc::IF_NAMESIZE],
+        ifr_data: &mut channels as *mut EthtoolChannels as *mut c_void,
+        _pad: [0; 16],
+    };
+    for (dst, src) in ifr.ifr_name.iter_mut().zip(if_name.as_bytes()) {
+        *dst = *src as c_char;
+    }
+
+    unsafe {
+        let fd = libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0);
+        if fd < 0 {
+            return Err(Error::Io(std::io::Error::last_os_error()));
+        }
+
+        let ret = libc::ioctl(fd, SIOCETHTOOL as _, &mut ifr);
+        let err = std::io::Error::last_os_error();
+        libc::close(fd);
+
+        // Drivers without channel support have a single queue.
+        if ret < 0 && err.raw_os_error() == Some(libc::EOPNOTSUPP) {
+            return Ok(1);
+        }
+        if ret < 0 {
+            return Err(Error::Interface {
+                interface: if_name.to_string(),
+                source: err,
+            });
+        }
+    }
+
+    /*
+     * Only RX-capable channels have a queue to bind. They are the combined channels,
+     * or the RX-only channels of drivers without combined ones. (ex. veth)
+     */
+    let count = match channels.combined_count {
+        0 => channels.rx_count,
+        combined => combined,
+    };
+    Ok((count as usize).max(1))
+}
+
+fn read_mtu(if_name: &str) -> Result<usize, Error> {
+    let path = format!("/sys/class/net/{}/mtu", if_name);
+    let error = |source| Error::Interface {
+        interface: if_name.to_string(),
+        source,
+    };
+    std::fs::read_to_string(path)
+        .map_err(error)?
+        .trim()
+        .parse::<usize>()
+        .map_err(|e| error(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_size() -> usize {
+        unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+    }
+
+    fn assert_invalid(result: Result<(), Error>, expected: &str) {
+        match result {
+            Err(Error::InvalidConfig(message)) => {
+                assert!(message.contains(expected), "{}", message)
+            }
+            other => panic!("expected InvalidConfig({}), got {:?}", expected, other),
+        }
+    }
+
+    #[test]
+    fn umem_config_default_is_valid() {
+        UmemConfig::default().validate().unwrap();
+        UmemConfig::default()
+            .chunk_size(page_size())
+            .chunk_count(64)
+            .fill_ring_size(64)
+            .completion_ring_size(64)
+            .numa_node(MAX_NUMA_NODES - 1)
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn umem_config_rejects_ring_sizes() {
+        for size in [0, 3, 100] {
+            assert_invalid(
+                UmemConfig::default().fill_ring_size(size).validate(),
+                "fill ring size must be a power of two",
+            );
+            assert_invalid(
+                UmemConfig::default().completion_ring_size(size).validate(),
+                "completion ring size must be a power of two",
+            );
+        }
+    }
+
+    #[test]
+    fn umem_config_rejects_chunk_size() {
+        for size in [
+            0,
+            MIN_CHUNK_SIZE / 2,
+            MIN_CHUNK_SIZE + 1,
+            3000,
+            page_size() * 2,
+        ] {
+            assert_invalid(
+                UmemConfig::default().chunk_size(size).validate(),
+                "chunk size must be a power of two",
+            );
+        }
+    }
+
+    #[test]
+    fn umem_config_rejects_rings_larger_than_chunk_count() {
+        assert_invalid(
+            UmemConfig::default()
+                .chunk_count(64)
+                .fill_ring_size(128)
+                .validate(),
+            "cover the fill ring. (Min = 128, Got 64)",
+        );
+        assert_invalid(
+            UmemConfig::default()
+                .chunk_count(64)
+                .completion_ring_size(128)
+                .validate(),
+            "cover the completion ring. (Min = 128, Got 64)",
+        );
+        assert_invalid(
+            UmemConfig::default().chunk_count(0).validate(),
+            "cover the fill ring",
+        );
+    }
+
+    #[test]
+    fn umem_config_rejects_numa_node() {
+        assert_invalid(
+            UmemConfig::default().numa_node(MAX_NUMA_NODES).validate(),
+            "NUMA node must be less than",
+        );
+    }
+
+    #[test]
+    fn nic_config_default_is_valid() {
+        NicConfig::default().validate().unwrap();
+        NicConfig::default()
+            .xdp_mode(XdpMode::Native)
+            .bind_mode(BindMode::ZeroCopy)
+            .divert(Match::udp_dst_port(7))
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn nic_config_rejects_ring_sizes() {
+        for size in [0, 3, 100] {
+            assert_invalid(
+                NicConfig::default().tx_ring_size(size).validate(),
+                "tx ring size must be a power of two",
+            );
+            assert_invalid(
+                NicConfig::default().rx_ring_size(size).validate(),
+                "rx ring size must be a power of two",
+            );
+        }
+    }
+
+    #[test]
+    fn nic_config_rejects_zero_copy_in_generic_mode() {
+        assert_invalid(
+            NicConfig::default()
+                .xdp_mode(XdpMode::Generic)
+                .bind_mode(BindMode::ZeroCopy)
+                .validate(),
+            "Zero-copy bind mode requires Native XDP mode",
+        );
+    }
+
+    #[test]
+    fn nic_config_rejects_divert_beyond_max_queues() {
+        let config = NicConfig::default().divert(Match::udp_dst_port(7));
+        config
+            .clone()
+            .queue_id(divert::MAX_QUEUES - 1)
+            .validate()
+            .unwrap();
+        assert_invalid(
+            config.queue_id(divert::MAX_QUEUES).validate(),
+            "serves queues below 64. (Got 64)",
+        );
+
+        // Without a divert program, any queue can be bound.
+        NicConfig::default()
+            .queue_id(divert::MAX_QUEUES)
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn nic_config_rejects_program_with_divert() {
+        assert_invalid(
+            NicConfig::default()
+                .xdp_program(XdpProgram::from_bytes(&[], "xdp"))
+                .divert(Match::udp_dst_port(7))
+                .validate(),
+            "cannot be used together",
+        );
     }
 }
@@ -0,0 +1,472 @@
+use crate::bindings::*;
+use crate::{read_xsk_stats, BufferPool, Nic, NicCounters, XskStats};
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/********************************************************************
+ *
+ * Structures
+ *
+ *******************************************************************/
+/// Prometheus exporter of `pv::Nic` and `pv::Umem` metrics
+///
+/// Serves the registered `pv::Nic`s in the Prometheus text format, labeled by
+/// interface and queue. A `pv::Nic` is removed once it is dropped.
+/// Available with the `metrics` feature.
+/// ```ignore
+/// let metrics = pv::Metrics::new();
+/// metrics.register(&nic);
+/// metrics.serve("127.0.0.1:9100")?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    nics: Arc<Mutex<Vec<Arc<NicProbe>>>>,
+}
+
+/// What the exporter reads of a `pv::Nic` from another thread.
+#[derive(Debug)]
+pub(crate) struct NicProbe {
+    interface: String,
+    queue_id: u32,
+    counters: Arc<NicCounters>,
+    buffer_pool: Weak<BufferPool>,
+    xsk: Mutex<Option<XskProbe>>, // None once the XSK is deleted.
+}
+
+/// XSK and ring indexes, valid until the XSK is deleted.
+#[derive(Debug)]
+struct XskProbe {
+    xsk: *mut xsk_socket,
+    rings: [RingProbe; 4],
+}
+
+#[derive(Debug)]
+struct RingProbe {
+    name: &'static str,
+    producer: *const AtomicU32,
+    consumer: *const AtomicU32,
+    size: u32,
+}
+
+struct Sample {
+    labels: String,
+    counters: [u64; COUNTERS.len()],
+    xsk_stats: Option<XskStats>,
+    free_chunks: Option<(usize, usize)>,  // (free, total)
+    rings: Vec<(&'static str, u32, u32)>, // (ring, entries, size)
+}
+
+// A client that stops reading or writing cannot hold the serving thread longer.
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+const COUNTERS: [(&str, &str); 9] = [
+    ("pv_rx_packets_total", "Received packets."),
+    ("pv_rx_bytes_total", "Received bytes."),
+    ("pv_tx_packets_total", "Packets put on the TX ring."),
+    ("pv_tx_bytes_total", "Bytes put on the TX ring."),
+    (
+        "pv_tx_partial_total",
+        "Sends that found fewer free TX ring slots than packets.",
+    ),
+    (
+        "pv_alloc_failures_total",
+        "Packet allocations that got fewer chunks than requested.",
+    ),
+    (
+        "pv_fill_shortfall_total",
+        "Chunks the fill ring lacked after refilling.",
+    ),
+    (
+        "pv_rx_wakeups_total",
+        "Syscalls made to wake up the kernel for RX.",
+    ),
+    (
+        "pv_tx_wakeups_total",
+        "Syscalls made to wake up the kernel for TX.",
+    ),
+];
+
+const XSK_STATS: [(&str, &str); 6] = [
+    (
+        "pv_xsk_rx_dropped_total",
+        "Packets dropped by the kernel for other reasons.",
+    ),
+    (
+        "pv_xsk_rx_invalid_descs_total",
+        "Packets dropped due to invalid fill ring descriptors.",
+    ),
+    (
+        "pv_xsk_tx_invalid_descs_total",
+        "Packets not sent due to invalid TX ring descriptors.",
+    ),
+    (
+        "pv_xsk_rx_ring_full_total",
+        "Packets dropped since the RX ring was full.",
+    ),
+    (
+        "pv_xsk_rx_fill_ring_empty_descs_total",
+        "Packets dropped since the fill ring was empty.",
+    ),
+    (
+        "pv_xsk_tx_ring_empty_descs_total",
+        "Times the kernel found the TX ring empty.",
+    ),
+];
+
+/********************************************************************
+ *
+ * Implementation
+ *
+ *******************************************************************/
+impl Metrics {
+    /// Empty exporter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Description
+    /// Export the metrics of `nic` until it is dropped. Registering it again has no effect.
+    /// # Arguments
+    /// `nic` - `pv::Nic` to export
+    pub fn register(&self, nic: &Nic) {
+        let mut nics = self.nics.lock().unwrap();
+        if !nics.iter().any(|probe| Arc::ptr_eq(probe, &nic.probe)) {
+            nics.push(nic.probe.clone());
+        }
+    }
+
+    /// # Description
+    /// Metrics of the registered `pv::Nic`s in the Prometheus text format. \
+    /// For applications serving `/metrics` by themselves.
+    pub fn render(&self) -> String {
+        render(&self.sample())
+    }
+
+    /// # Description
+    /// Serve `GET /metrics` on `addr` in a new thread
+    /// # Arguments
+    /// `addr` - Address to listen on. (ex. `127.0.0.1:9100`)
+    /// # Returns
+    /// On success, returns the handle of the serving thread. \
+    /// On failure, returns the error of binding `addr`.
+    pub fn serve<A: ToSocketAddrs>(&self, addr: A) -> io::Result<JoinHandle<()>> {
+        let listener = TcpListener::bind(addr)?;
+        let metrics = self.clone();
+
+        Ok(thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = metrics.respond(stream) {
+                    eprintln!("Failed to serve metrics: {}", e);
+                }
+            }
+        }))
+    }
+
+    fn respond(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+        let mut reader = BufReader::new(&stream);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+
+        // Skip the headers.
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 2 {
+            line.clear();
+        }
+
+        let mut parts = request.split_whitespace();
+        let (status, body) = match (parts.next(), parts.next()) {
+            (Some("GET"), Some("/metrics")) => ("200 OK", self.render()),
+            _ => ("404 Not Found", String::new()),
+        };
+
+        let mut stream = &stream;
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+
+    /// Read every registered Nic, and forget the dropped ones.
+    fn sample(&self) -> Vec<Sample> {
+        let mut nics = self.nics.lock().unwrap();
+        let mut samples = Vec::with_capacity(nics.len());
+
+        nics.retain(|probe| match probe.sample() {
+            Some(sample) => {
+                samples.push(sample);
+                true
+            }
+            None => false,
+        });
+
+        samples
+    }
+}
+
+impl NicProbe {
+    pub(crate) fn new(
+        interface: &str,
+        queue_id: u32,
+        counters: &Arc<NicCounters>,
+        buffer_pool: &Arc<BufferPool>,
+    ) -> Self {
+        Self {
+            interface: interface.to_string(),
+            queue_id,
+            counters: counters.clone(),
+            buffer_pool: Arc::downgrade(buffer_pool),
+            xsk: Mutex::new(None),
+        }
+    }
+
+    /// Start reading the XSK of `nic`, once it is created.
+    pub(crate) fn open(&self, nic: &Nic) {
+        let rings = [
+            RingProbe::new("rx", nic.rxq.producer, nic.rxq.consumer, nic.rxq.size),
+            RingProbe::new("tx", nic.txq.producer, nic.txq.consumer, nic.txq.size),
+            RingProbe::new(
+                "fill",
+                nic.umem_fq.producer,
+                nic.umem_fq.consumer,
+                nic.umem_fq.size,
+            ),
+            RingProbe::new(
+                "completion",
+                nic.umem_cq.producer,
+                nic.umem_cq.consumer,
+                nic.umem_cq.size,
+            ),
+        ];
+
+        *self.xsk.lock().unwrap() = Some(XskProbe {
+            xsk: nic.xsk,
+            rings,
+        });
+    }
+
+    /// Stop reading the XSK, before it is deleted.
+    pub(crate) fn close(&self) {
+        *self.xsk.lock().unwrap() = None;
+    }
+
+    fn sample(&self) -> Option<Sample> {
+        // Held while reading, so that the XSK is not deleted in the meantime.
+        let xsk = self.xsk.lock().unwrap();
+        let xsk = xsk.as_ref()?;
+
+        let counters = &self.counters;
+        Some(Sample {
+            labels: format!(
+                "interface=\"{}\",queue=\"{}\"",
+                self.interface, self.queue_id
+            ),
+            counters: [
+                counters.rx_packets(),
+                counters.rx_bytes(),
+                counters.tx_packets(),
+                counters.tx_bytes(),
+                counters.tx_partial(),
+                counters.alloc_failures(),
+                counters.fill_shortfall(),
+                counters.rx_wakeups(),
+                counters.tx_wakeups(),
+            ],
+            xsk_stats: read_xsk_stats(xsk.xsk).ok(),
+            free_chunks: self
+                .buffer_pool
+                .upgrade()
+                .map(|pool| (pool.free_count(), pool.chunk_count)),
+            rings: xsk
+                .rings
+                .iter()
+                .map(|ring| (ring.name, ring.entries(), ring.size))
+                .collect(),
+        })
+    }
+}
+
+impl RingProbe {
+    fn new(name: &'static str, producer: *mut u32, consumer: *mut u32, size: u32) -> Self {
+        Self {
+            name,
+            producer: producer.cast(),
+            consumer: consumer.cast(),
+            size,
+        }
+    }
+
+    /// Entries between the consumer and the producer.
+    fn entries(&self) -> u32 {
+        let (producer, consumer) = unsafe {
+            (
+                (*self.producer).load(Ordering::Relaxed),
+                (*self.consumer).load(Ordering::Relaxed),
+            )
+        };
+
+        producer.wrapping_sub(consumer).min(self.size)
+    }
+}
+
+// The pointers are only read while the XSK exists, see NicProbe::close().
+unsafe impl Send for XskProbe {}
+
+/********************************************************************
+ *
+ * Other functions
+ *
+ *******************************************************************/
+/// `samples` in the Prometheus text format.
+fn render(samples: &[Sample]) -> String {
+    let mut out = String::new();
+
+    for (i, (name, help)) in COUNTERS.iter().enumerate() {
+        header(&mut out, name, help, "counter");
+        for sample in samples {
+            let _ = writeln!(out, "{}{{{}}} {}", name, sample.labels, sample.counters[i]);
+        }
+    }
+
+    for (i, (name, help)) in XSK_STATS.iter().enumerate() {
+        header(&mut out, name, help, "counter");
+        for sample in samples {
+            if let Some(stats) = &sample.xsk_stats {
+                let value = [
+                    stats.rx_dropped,
+                    stats.rx_invalid_descs,
+                    stats.tx_invalid_descs,
+                    stats.rx_ring_full,
+                    stats.rx_fill_ring_empty_descs,
+                    stats.tx_ring_empty_descs,
+                ][i];
+                let _ = writeln!(out, "{}{{{}}} {}", name, sample.labels, value);
+            }
+        }
+    }
+
+    // Nics sharing a pv::Umem report the same values.
+    for (name, help, in_use) in [
+        (
+            "pv_umem_free_chunks",
+            "Chunks of the UMEM free in the pool.",
+            false,
+        ),
+        (
+            "pv_umem_used_chunks",
+            "Chunks of the UMEM held by the application or the kernel.",
+            true,
+        ),
+    ] {
+        header(&mut out, name, help, "gauge");
+        for sample in samples {
+            if let Some((free, total)) = sample.free_chunks {
+                let value = if in_use { total - free } else { free };
+                let _ = writeln!(out, "{}{{{}}} {}", name, sample.labels, value);
+            }
+        }
+    }
+
+    for (name, help, size) in [
+        ("pv_ring_entries", "Entries in the XSK ring.", false),
+        ("pv_ring_size", "Size of the XSK ring.", true),
+    ] {
+        header(&mut out, name, help, "gauge");
+        for sample in samples {
+            for (ring, entries, ring_size) in &sample.rings {
+                let value = if size { *ring_size } else { *entries };
+                let _ = writeln!(
+                    out,
+                    "{}{{{},ring=\"{}\"}} {}",
+                    name, sample.labels, ring, value
+                );
+            }
+        }
+    }
+
+    out
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(queue_id: u32) -> Sample {
+        Sample {
+            labels: format!("interface=\"veth0\",queue=\"{}\"", queue_id),
+            counters: [1, 2, 3, 4, 5, 6, 7, 8, 9],
+            xsk_stats: Some(XskStats {
+                rx_dropped: 10,
+                rx_ring_full: 11,
+                ..Default::default()
+            }),
+            free_chunks: Some((3000, 4096)),
+            rings: vec![("rx", 12, 2048), ("fill", 1024, 2048)],
+        }
+    }
+
+    #[test]
+    fn render_text_format() {
+        let out = render(&[sample(0), sample(1)]);
+        let lines: Vec<&str> = out.lines().collect();
+
+        for expected in [
+            "# HELP pv_rx_packets_total Received packets.",
+            "# TYPE pv_rx_packets_total counter",
+            "pv_rx_packets_total{interface=\"veth0\",queue=\"0\"} 1",
+            "pv_rx_packets_total{interface=\"veth0\",queue=\"1\"} 1",
+            "pv_tx_wakeups_total{interface=\"veth0\",queue=\"0\"} 9",
+            "pv_xsk_rx_dropped_total{interface=\"veth0\",queue=\"0\"} 10",
+            "pv_xsk_rx_ring_full_total{interface=\"veth0\",queue=\"1\"} 11",
+            "pv_xsk_tx_invalid_descs_total{interface=\"veth0\",queue=\"0\"} 0",
+            "# TYPE pv_umem_free_chunks gauge",
+            "pv_umem_free_chunks{interface=\"veth0\",queue=\"0\"} 3000",
+            "pv_umem_used_chunks{interface=\"veth0\",queue=\"0\"} 1096",
+            "pv_ring_entries{interface=\"veth0\",queue=\"0\",ring=\"rx\"} 12",
+            "pv_ring_size{interface=\"veth0\",queue=\"1\",ring=\"fill\"} 2048",
+        ] {
+            assert!(lines.contains(&expected), "missing {}", expected);
+        }
+
+        // One HELP and TYPE per metric, and every other line is a sample.
+        let metrics = COUNTERS.len() + XSK_STATS.len() + 4;
+        assert_eq!(
+            lines.iter().filter(|l| l.starts_with("# HELP ")).count(),
+            metrics
+        );
+        assert_eq!(
+            lines.iter().filter(|l| l.starts_with("# TYPE ")).count(),
+            metrics
+        );
+        for line in lines.iter().filter(|l| !l.starts_with('#')) {
+            let (series, value) = line.rsplit_once(' ').unwrap();
+            assert!(
+                series.starts_with("pv_") && series.ends_with('}'),
+                "{}",
+                line
+            );
+            assert!(value.parse::<u64>().is_ok(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn render_without_nics() {
+        let out = Metrics::new().render();
+        assert!(out.lines().all(|l| l.starts_with("# ")));
+    }
+}
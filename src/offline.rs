@@ -0,0 +1,311 @@
+//! pcap-backed `pv::Nic` replacement for deterministic tests
+//!
+//! `OfflineNic` receives the frames of an input pcap or pcapng file and writes
+//! the sent packets to an output file. It uses the same `pv::Packet` and implements
+//! `pv::PacketIo`, so packet processing code generic over `pv::PacketIo` runs
+//! unchanged without root, XDP or a network interface.
+//! ```ignore
+//! fn echo<N: pv::PacketIo>(nic: &mut N) {
+//!     let mut packets = nic.receive(64);
+//!     packets.retain_mut(|p| process_packet(p, nic.interface()));
+//!     nic.send(&mut packets);
+//! }
+//!
+//! let umem = pv::Umem::offline(&pv::UmemConfig::default())?;
+//! let config = pv::offline::OfflineConfig::default().output("out.pcap");
+//! let mut nic = pv::offline::OfflineNic::new("veth1", "in.pcap", &umem, &config)?;
+//! while !nic.is_exhausted() {
+//!     echo(&mut nic);
+//! }
+//! nic.flush()?;
+//! ```
+
+use crate::capture::{CaptureConfig, CaptureFormat, PcapReader, PcapWriter};
+use crate::{Error, NicCounters, Packet, PacketIo, Umem};
+use pnet::datalink::{MacAddr, NetworkInterface};
+use pnet::ipnetwork::IpNetwork;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/********************************************************************
+ *
+ * Structures
+ *
+ *******************************************************************/
+/// Configuration of `OfflineNic`
+#[derive(Debug, Clone)]
+pub struct OfflineConfig {
+    output: Option<PathBuf>,
+    format: CaptureFormat,
+    mac: Option<MacAddr>,
+    ips: Vec<IpNetwork>,
+}
+
+/// `pv::Nic` replacement reading and writing pcap files
+///
+/// Implements `pv::PacketIo`, and has the same packet I/O methods as `pv::Nic`.
+/// Sent packets are written with the timestamp of the last received frame,
+/// so the same input always gives the same output file.
+#[derive(Debug)]
+pub struct OfflineNic {
+    /// Interface information given in `OfflineNic::new()` and `OfflineConfig`.
+    pub interface: NetworkInterface,
+    umem: Umem,
+    input: PcapReader,
+    output: Option<PcapWriter>,
+    time: Duration, // timestamp of the last received frame.
+    exhausted: bool,
+    counters: Arc<NicCounters>,
+}
+
+/********************************************************************
+ *
+ * Implementation
+ *
+ *******************************************************************/
+impl Default for OfflineConfig {
+    fn default() -> Self {
+        Self {
+            output: None,
+            format: CaptureFormat::Pcap,
+            mac: None,
+            ips: Vec::new(),
+        }
+    }
+}
+
+impl OfflineConfig {
+    /// File to write the sent packets to. Without it, they are dropped.
+    pub fn output<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.output = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Format of the output file. Default is `CaptureFormat::Pcap`.
+    pub fn output_format(mut self, format: CaptureFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// MAC address reported in `OfflineNic::interface`.
+    pub fn mac(mut self, mac: MacAddr) -> Self {
+        self.mac = Some(mac);
+        self
+    }
+
+    /// IP address reported in `OfflineNic::interface`. Can be called several times.
+    pub fn ip(mut self, ip: IpNetwork) -> Self {
+        self.ips.push(ip);
+        self
+    }
+}
+
+impl OfflineNic {
+    /// # Description
+    /// Open `input` as the receive side
+    /// # Arguments
+    /// `if_name` - Interface name reported in `OfflineNic::interface` and the output file \
+    /// `input` - pcap or pcapng file of Ethernet frames \
+    /// `umem` - UMEM to allocate packets from, usually from `pv::Umem::offline()` \
+    /// `config` - See `OfflineConfig`
+    /// # Returns
+    /// On success, returns `OfflineNic`. \
+    /// On failure, returns `pv::Error`.
+    pub fn new<P: AsRef<Path>>(
+        if_name: &str,
+        input: P,
+        umem: &Umem,
+        config: &OfflineConfig,
+    ) -> Result<OfflineNic, Error> {
+        let input = PcapReader::open(input)?;
+        let output = match &config.output {
+            Some(path) => Some(PcapWriter::new(
+                path,
+                &CaptureConfig::default().format(config.format),
+            )?),
+            None => None,
+        };
+
+        Ok(OfflineNic {
+            interface: NetworkInterface {
+                name: if_name.to_string(),
+                description: String::new(),
+                index: 0,
+                mac: config.mac,
+                ips: config.ips.clone(),
+                flags: (libc::IFF_UP | libc::IFF_RUNNING) as u32,
+            },
+            umem: umem.clone(),
+            input,
+            output,
+            time: Duration::ZERO,
+            exhausted: false,
+            counters: Arc::new(NicCounters::default()),
+        })
+    }
+
+    /// # Description
+    /// UMEM this `OfflineNic` uses.
+    pub fn umem(&self) -> &Umem {
+        &self.umem
+    }
+
+    /// # Description
+    /// Allocate packet from the UMEM of this `OfflineNic`. See `pv::Nic::alloc_packet()`.
+    pub fn alloc_packet(&self) -> Option<Packet> {
+        let packet = self.umem.alloc_packet();
+        if packet.is_none() {
+            NicCounters::add(&self.counters.alloc_failures, 1);
+        }
+
+        packet
+    }
+
+    /// # Description
+    /// Allocate `len` packets at once. See `pv::Nic::alloc_packets()`.
+    pub fn alloc_packets(&self, len: usize) -> Option<Vec<Packet>> {
+        let packets = self.umem.alloc_packets(len);
+        if packets.is_none() {
+            NicCounters::add(&self.counters.alloc_failures, 1);
+        }
+
+        packets
+    }
+
+    /// # Description
+    /// Allocate as many packets as possible, up to `len`. See `pv::Nic::alloc_packets_into()`.
+    pub fn alloc_packets_into(&self, packets: &mut Vec<Packet>, len: usize) -> usize {
+        let count = self.umem.alloc_packets_into(packets, len);
+        if count < len {
+            NicCounters::add(&self.counters.alloc_failures, 1);
+        }
+
+        count
+    }
+
+    /// # Description
+    /// Receive the next frames of the input file
+    /// # Arguments
+    /// `len` - Number of packets to receive
+    /// # Returns
+    /// Received packets. Empty at the end of the input or if the UMEM is exhausted.
+    pub fn receive(&mut self, len: usize) -> Vec<Packet> {
+        let mut packets = Vec::with_capacity(len);
+
+        while packets.len() < len && !self.exhausted {
+            let Some(mut packet) = self.alloc_packet() else {
+                break;
+            };
+
+            let frame = match self.input.next_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    self.exhausted = true;
+                    break;
+                }
+                Err(e) => {
+                    eprintln!("Failed to read {}: {}", self.interface.name, e);
+                    self.exhausted = true;
+                    break;
+                }
+            };
+
+            self.time = frame.time;
+            if let Err(e) = packet.replace_data(&frame.data) {
+                eprintln!("Skipped a frame: {}", e);
+                continue;
+            }
+
+            NicCounters::add(&self.counters.rx_bytes, frame.data.len() as u64);
+            packets.push(packet);
+        }
+
+        NicCounters::add(&self.counters.rx_packets, packets.len() as u64);
+        packets
+    }
+
+    /// # Description
+    /// Same as `receive()`, as the input file never has to be waited for.
+    pub fn receive_timeout(&mut self, len: usize, _timeout: Duration) -> Vec<Packet> {
+        self.receive(len)
+    }
+
+    /// # Description
+    /// Write packets to the output file \
+    /// **\*Sent packets are removed from the vector.**
+    /// # Arguments
+    /// `packets` - Packets to send
+    /// # Returns
+    /// Number of packets sent, which is always all of them
+    pub fn send(&mut self, packets: &mut Vec<Packet>) -> usize {
+        let count = packets.len();
+
+        for packet in packets.drain(..) {
+            NicCounters::add(&self.counters.tx_bytes, packet.get_buffer().len() as u64);
+            if let Some(output) = &mut self.output {
+                if let Err(e) = output.write_at(&packet, &self.interface.name, self.time) {
+                    eprintln!("Failed to write {}: {}", self.interface.name, e);
+                }
+            }
+        }
+
+        NicCounters::add(&self.counters.tx_packets, count as u64);
+        count
+    }
+
+    /// # Description
+    /// Whether every frame of the input file has been received.
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted
+    }
+
+    /// # Description
+    /// Counters of this `OfflineNic`. See `pv::Nic::counters()`.
+    pub fn counters(&self) -> Arc<NicCounters> {
+        self.counters.clone()
+    }
+
+    /// # Description
+    /// Flush the output file. It is also flushed when `OfflineNic` is dropped.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        match &mut self.output {
+            Some(output) => output.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl PacketIo for OfflineNic {
+    fn interface(&self) -> &NetworkInterface {
+        &self.interface
+    }
+
+    fn alloc_packet(&self) -> Option<Packet> {
+        OfflineNic::alloc_packet(self)
+    }
+
+    fn alloc_packets(&self, len: usize) -> Option<Vec<Packet>> {
+        OfflineNic::alloc_packets(self, len)
+    }
+
+    fn alloc_packets_into(&self, packets: &mut Vec<Packet>, len: usize) -> usize {
+        OfflineNic::alloc_packets_into(self, packets, len)
+    }
+
+    fn receive(&mut self, len: usize) -> Vec<Packet> {
+        OfflineNic::receive(self, len)
+    }
+
+    fn receive_timeout(&mut self, len: usize, timeout: Duration) -> Vec<Packet> {
+        OfflineNic::receive_timeout(self, len, timeout)
+    }
+
+    fn send(&mut self, packets: &mut Vec<Packet>) -> usize {
+        OfflineNic::send(self, packets)
+    }
+
+    fn counters(&self) -> Arc<NicCounters> {
+        OfflineNic::counters(self)
+    }
+}
@@ -0,0 +1,273 @@
+use crate::bindings::*;
+use crate::{Error, XdpMode};
+use std::ffi::{c_char, c_int, c_void, CString};
+use std::path::{Path, PathBuf};
+
+const DEFAULT_XSKMAP: &str = "xsks_map";
+
+/********************************************************************
+ *
+ * Structures
+ *
+ *******************************************************************/
+/// XDP program attached by `pv::Nic` instead of the libxdp default redirect program
+///
+/// The program must have an `XSKMAP` (named `xsks_map` by default) and redirect
+/// packets into it by RX queue index. `pv::Nic` registers its XSK in the map.
+/// ```ignore
+/// let config = pv::NicConfig::default()
+///     .xdp_program(pv::XdpProgram::from_file("filter.o", "xdp"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct XdpProgram {
+    source: Source,
+    section: String,
+    xskmap: String,
+}
+
+/// Action returned by an XDP program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XdpAction {
+    Aborted,
+    Drop,
+    Pass,
+    Tx,
+    Redirect,
+}
+
+/// Position and chain-call actions of a program in the libxdp dispatcher
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ChainConfig {
+    pub(crate) run_priority: Option<u32>,
+    pub(crate) chain_calls: Vec<(XdpAction, bool)>,
+}
+
+#[derive(Debug, Clone)]
+enum Source {
+    File(PathBuf),
+    Memory(Vec<u8>),
+}
+
+/// XDP program attached to a network interface. Detached on drop.
+#[derive(Debug)]
+pub(crate) struct AttachedProgram {
+    prog: *mut xdp_program,
+    obj: *mut bpf_object, // owned only when opened from memory.
+    ifindex: c_int,
+    mode: xdp_attach_mode,
+    xskmap: String,
+}
+
+/********************************************************************
+ *
+ * Implementation
+ *
+ *******************************************************************/
+impl XdpProgram {
+    /// # Description
+    /// XDP program from a BPF object file
+    /// # Arguments
+    /// `path` - path of the BPF object file \
+    /// `section` - ELF section of the program (ex. `xdp`)
+    pub fn from_file<P: AsRef<Path>>(path: P, section: &str) -> XdpProgram {
+        XdpProgram {
+            source: Source::File(path.as_ref().to_path_buf()),
+            section: section.to_string(),
+            xskmap: DEFAULT_XSKMAP.to_string(),
+        }
+    }
+
+    /// # Description
+    /// XDP program from an in-memory BPF object (ELF)
+    /// # Arguments
+    /// `elf` - content of the BPF object file \
+    /// `section` - ELF section of the program (ex. `xdp`)
+    pub fn from_bytes(elf: &[u8], section: &str) -> XdpProgram {
+        XdpProgram {
+            source: Source::Memory(elf.to_vec()),
+            section: section.to_string(),
+            xskmap: DEFAULT_XSKMAP.to_string(),
+        }
+    }
+
+    /// Name of the `XSKMAP` to register the XSK in. Default is `xsks_map`.
+    pub fn xskmap(mut self, name: &str) -> Self {
+        self.xskmap = name.to_string();
+        self
+    }
+
+    /// Open the program and attach it to `ifindex` through the libxdp dispatcher.
+    /// `XdpMode::Auto` tries Native mode first and falls back to Generic mode.
+    pub(crate) fn attach(
+        &self,
+        ifindex: u32,
+        mode: XdpMode,
+        chain: &ChainConfig,
+    ) -> Result<AttachedProgram, Error> {
+        let section = CString::new(self.section.clone()).unwrap();
+        let mut obj = std::ptr::null_mut();
+
+        let prog = match &self.source {
+            Source::File(path) => {
+                let filename = CString::new(path.to_string_lossy().into_owned()).unwrap();
+                unsafe {
+                    xdp_program__open_file(
+                        filename.as_ptr() as *const c_char,
+                        section.as_ptr() as *const c_char,
+                        std::ptr::null_mut(),
+                    )
+                }
+            }
+            Source::Memory(elf) => {
+                obj = unsafe {
+                    bpf_object__open_mem(elf.as_ptr() as *const c_void, elf.len(), std::ptr::null())
+                };
+                if obj.is_null() {
+                    return Err(Error::XdpProgram {
+                        context: "Failed to open BPF object".to_string(),
+                        source: std::io::Error::last_os_error(),
+                    });
+                }
+                unsafe { xdp_program__from_bpf_obj(obj, section.as_ptr() as *const c_char) }
+            }
+        };
+
+        let ret = unsafe { libxdp_get_error(prog as *const c_void) };
+        if ret != 0 {
+            if !obj.is_null() {
+                unsafe { bpf_object__close(obj) };
+            }
+            return Err(Error::XdpProgram {
+                context: format!("Failed to open XDP program {}", self.section),
+                source: Error::from_errno(ret as c_int),
+            });
+        }
+
+        if let Some(run_priority) = chain.run_priority {
+            unsafe { xdp_program__set_run_prio(prog, run_priority) };
+        }
+        for &(action, enabled) in &chain.chain_calls {
+            unsafe { xdp_program__set_chain_call_enabled(prog, action.into(), enabled) };
+        }
+
+        let mut attached = AttachedProgram {
+            prog,
+            obj,
+            ifindex: ifindex as c_int,
+            mode: xdp_attach_mode_XDP_MODE_UNSPEC,
+            xskmap: self.xskmap.clone(),
+        };
+
+        let modes: &[xdp_attach_mode] = match mode {
+            XdpMode::Auto => &[
+                xdp_attach_mode_XDP_MODE_NATIVE,
+                xdp_attach_mode_XDP_MODE_SKB,
+            ],
+            XdpMode::Native => &[xdp_attach_mode_XDP_MODE_NATIVE],
+            XdpMode::Generic => &[xdp_attach_mode_XDP_MODE_SKB],
+        };
+
+        let mut ret = 0;
+        for &mode in modes {
+            ret = unsafe { xdp_program__attach(prog, ifindex as c_int, mode, 0) };
+            if ret == 0 {
+                attached.mode = mode;
+                return Ok(attached);
+            }
+        }
+
+        Err(Error::XdpProgram {
+            context: format!("Failed to attach XDP program {}", self.section),
+            source: Error::from_errno(ret),
+        })
+    }
+}
+
+impl ChainConfig {
+    /// `true` if nothing differs from the libxdp defaults.
+    pub(crate) fn is_default(&self) -> bool {
+        self.run_priority.is_none() && self.chain_calls.is_empty()
+    }
+}
+
+impl From<XdpAction> for u32 {
+    fn from(action: XdpAction) -> u32 {
+        match action {
+            XdpAction::Aborted => xdp_action_XDP_ABORTED,
+            XdpAction::Drop => xdp_action_XDP_DROP,
+            XdpAction::Pass => xdp_action_XDP_PASS,
+            XdpAction::Tx => xdp_action_XDP_TX,
+            XdpAction::Redirect => xdp_action_XDP_REDIRECT,
+        }
+    }
+}
+
+impl AttachedProgram {
+    /// XDP mode the program is attached in.
+    pub(crate) fn mode(&self) -> XdpMode {
+        if self.mode == xdp_attach_mode_XDP_MODE_SKB {
+            XdpMode::Generic
+        } else {
+            XdpMode::Native
+        }
+    }
+
+    /// File descriptor of the map `name` in the program.
+    pub(crate) fn map_fd(&self, name: &str) -> Result<c_int, Error> {
+        let map_name = CString::new(name).unwrap();
+        let map_fd = unsafe {
+            bpf_object__find_map_fd_by_name(
+                xdp_program__bpf_obj(self.prog),
+                map_name.as_ptr() as *const c_char,
+            )
+        };
+        if map_fd < 0 {
+            return Err(Error::XdpProgram {
+                context: format!("Map {} not found in XDP program", name),
+                source: Error::from_errno(map_fd),
+            });
+        }
+
+        Ok(map_fd)
+    }
+
+    /// Register `xsk` in the program's XSKMAP at its queue id.
+    pub(crate) fn register(&self, xsk: *mut xsk_socket) -> Result<(), Error> {
+        let map_fd = self.map_fd(&self.xskmap)?;
+
+        let ret = unsafe { xsk_socket__update_xskmap(xsk, map_fd) };
+        if ret != 0 {
+            return Err(Error::XdpProgram {
+                context: format!("Failed to update XSKMAP {}", self.xskmap),
+                source: Error::from_errno(ret),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+// Shared by the Nics of a NicGroup. Only the XSKMAP is updated after attaching.
+unsafe impl Send for AttachedProgram {}
+unsafe impl Sync for AttachedProgram {}
+
+/********************************************************************
+ *
+ * Drop
+ *
+ *******************************************************************/
+impl Drop for AttachedProgram {
+    fn drop(&mut self) {
+        unsafe {
+            if self.mode != xdp_attach_mode_XDP_MODE_UNSPEC
+                && xdp_program__detach(self.prog, self.ifindex, self.mode, 0) != 0
+            {
+                eprintln!("failed to detach XDP program");
+            }
+            xdp_program__close(self.prog);
+            if !self.obj.is_null() {
+                bpf_object__close(self.obj);
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! PcapWriter output, read back with PcapReader.
+
+use pv::capture::{CaptureConfig, CaptureFormat, Frame, PcapReader, PcapWriter};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const FRAME_SIZE: usize = 100;
+
+/// Empty directory for the files of one test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pv-capture-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn umem() -> pv::Umem {
+    pv::Umem::offline(&pv::UmemConfig::default().chunk_count(64)).unwrap()
+}
+
+fn packet(umem: &pv::Umem, seq: usize, len: usize) -> pv::Packet {
+    let mut packet = umem.alloc_packet().unwrap();
+    let data: Vec<u8> = (0..len).map(|i| (seq + i) as u8).collect();
+    packet.replace_data(&data).unwrap();
+    packet
+}
+
+fn read_all(path: &Path) -> Vec<Frame> {
+    let mut reader = PcapReader::open(path).unwrap();
+    let mut frames = Vec::new();
+    while let Some(frame) = reader.next_frame().unwrap() {
+        frames.push(frame);
+    }
+    frames
+}
+
+/// Files written with rotation: `rx.pcap`, `rx-1.pcap`, ... in order
+fn rotated_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![dir.join("rx.pcap")];
+    while dir.join(format!("rx-{}.pcap", files.len())).exists() {
+        files.push(dir.join(format!("rx-{}.pcap", files.len())));
+    }
+
+    let count = std::fs::read_dir(dir).unwrap().count();
+    assert_eq!(count, files.len(), "unexpected file names in {:?}", dir);
+    files
+}
+
+#[test]
+fn rotate_by_size() {
+    let dir = temp_dir("size");
+    let umem = umem();
+    let rotate_size = 1000;
+
+    let config = CaptureConfig::default().rotate_size(rotate_size);
+    let mut writer = PcapWriter::new(dir.join("rx.pcap"), &config).unwrap();
+    for seq in 0..30 {
+        let time = Duration::from_millis(seq as u64);
+        assert!(writer
+            .write_at(&packet(&umem, seq, FRAME_SIZE), "rx0", time)
+            .unwrap());
+    }
+    drop(writer);
+
+    // 24 bytes of header, then 16 bytes of header per record.
+    let files = rotated_files(&dir);
+    assert_eq!(files.len(), 4);
+    let mut seq = 0;
+    for (i, file) in files.iter().enumerate() {
+        let size = std::fs::metadata(file).unwrap().len();
+        if i < files.len() - 1 {
+            assert!(size >= rotate_size, "{:?} is {} bytes", file, size);
+        }
+        assert!(size < rotate_size + 16 + FRAME_SIZE as u64);
+
+        for frame in read_all(file) {
+            assert_eq!(frame.data, packet(&umem, seq, FRAME_SIZE).get_buffer());
+            assert_eq!(frame.time, Duration::from_millis(seq as u64));
+            seq += 1;
+        }
+    }
+    assert_eq!(seq, 30);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn rotate_by_interval() {
+    let dir = temp_dir("interval");
+    let umem = umem();
+
+    let config = CaptureConfig::default().rotate_interval(Duration::from_millis(50));
+    let mut writer = PcapWriter::new(dir.join("rx.pcap"), &config).unwrap();
+    for seq in 0..3 {
+        writer
+            .write(&packet(&umem, seq, FRAME_SIZE), "rx0")
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(80));
+    }
+    drop(writer);
+
+    let files = rotated_files(&dir);
+    assert_eq!(files.len(), 3);
+    for (seq, file) in files.iter().enumerate() {
+        let frames = read_all(file);
+        assert_eq!(frames.len(), 1, "{:?}", file);
+        assert_eq!(frames[0].data, packet(&umem, seq, FRAME_SIZE).get_buffer());
+    }
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn snaplen_truncates() {
+    let dir = temp_dir("snaplen");
+    let umem = umem();
+
+    for (format, name) in [
+        (CaptureFormat::Pcap, "rx.pcap"),
+        (CaptureFormat::Pcapng, "rx.pcapng"),
+    ] {
+        let path = dir.join(name);
+        let config = CaptureConfig::default().format(format).snaplen(64);
+        let mut writer = PcapWriter::new(&path, &config).unwrap();
+        writer.write(&packet(&umem, 0, 200), "rx0").unwrap();
+        writer.write(&packet(&umem, 1, 40), "rx0").unwrap();
+        drop(writer);
+
+        let frames = read_all(&path);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].orig_len, 200, "{:?}", format);
+        assert_eq!(frames[0].data, packet(&umem, 0, 200).get_buffer()[..64]);
+        assert_eq!(frames[1].orig_len, 40, "{:?}", format);
+        assert_eq!(frames[1].data, packet(&umem, 1, 40).get_buffer());
+    }
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn non_blocking_drops_when_full() {
+    let dir = temp_dir("non-blocking");
+    let umem = umem();
+    let path = dir.join("rx.pcap");
+    let count = 20_000;
+
+    let config = CaptureConfig::default().non_blocking(true).queue_size(1);
+    let mut writer = PcapWriter::new(&path, &config).unwrap();
+    let packet = packet(&umem, 0, 1500);
+    let mut written = 0;
+    for _ in 0..count {
+        if writer.write(&packet, "rx0").unwrap() {
+            written += 1;
+        }
+    }
+    let dropped = writer.dropped();
+    drop(writer);
+
+    assert!(dropped > 0, "nothing dropped with a queue of 1");
+    assert_eq!(written + dropped, count);
+    assert_eq!(read_all(&path).len() as u64, written);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
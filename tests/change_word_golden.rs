@@ -0,0 +1,120 @@
+//! The change_word example's processing against golden pcap files, without root.
+//!
+//! `data/change_word-in.pcap` holds UDP datagrams with and without the word "hello",
+//! and a TCP segment the example must forward as is. `data/change_word-longer-out.pcap`
+//! and `data/change_word-shorter-out.pcap` hold the expected frames with "hello"
+//! changed to a longer and a shorter word. After an intended change of the example,
+//! regenerate them with:
+//! ```sh
+//! PV_UPDATE_GOLDEN=1 cargo test --test change_word_golden
+//! ```
+
+#[path = "../examples/change_word/process.rs"]
+mod process;
+
+use pnet::packet::ethernet::EthernetPacket;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::udp::{self, UdpPacket};
+use pnet::packet::Packet;
+use pv::capture::{CaptureConfig, PcapReader, PcapWriter};
+use pv::offline::{OfflineConfig, OfflineNic};
+use std::path::{Path, PathBuf};
+
+fn data(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
+
+fn temp(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("pv-{}-{}", std::process::id(), name))
+}
+
+fn frames(path: &Path) -> Vec<Vec<u8>> {
+    let mut reader = PcapReader::open(path).unwrap();
+    let mut frames = Vec::new();
+    while let Some(frame) = reader.next_frame().unwrap() {
+        frames.push(frame.data);
+    }
+    frames
+}
+
+/// Forward `change_word-in.pcap` with `source` changed to `target` and compare the
+/// output with the golden file `name`.
+fn change_word(source: &str, target: &str, name: &str) -> Vec<Vec<u8>> {
+    let umem = pv::Umem::offline(&pv::UmemConfig::default().chunk_count(64)).unwrap();
+    let output = temp(name);
+
+    // The forwarding side only sends.
+    let empty = temp(&format!("{}-empty", name));
+    drop(PcapWriter::new(&empty, &CaptureConfig::default()).unwrap());
+
+    let mut nic1 = OfflineNic::new(
+        "veth1",
+        data("change_word-in.pcap"),
+        &umem,
+        &OfflineConfig::default(),
+    )
+    .unwrap();
+    let mut nic2 = OfflineNic::new(
+        "veth2",
+        &empty,
+        &umem,
+        &OfflineConfig::default().output(&output),
+    )
+    .unwrap();
+    while !nic1.is_exhausted() {
+        process::forward(&mut nic1, &mut nic2, source, target);
+    }
+    drop(nic1);
+    drop(nic2);
+    assert_eq!(umem.free_chunks(), umem.chunk_count());
+
+    let golden = data(name);
+    if std::env::var_os("PV_UPDATE_GOLDEN").is_some() {
+        std::fs::copy(&output, &golden).unwrap();
+    }
+
+    // Compare the frames first, for a readable failure.
+    let expected = frames(&golden);
+    let sent = frames(&output);
+    for (i, (frame, expected)) in sent.iter().zip(&expected).enumerate() {
+        assert_eq!(frame, expected, "{} frame {}", name, i);
+    }
+    assert_eq!(sent.len(), expected.len(), "{}", name);
+    assert_eq!(
+        std::fs::read(&output).unwrap(),
+        std::fs::read(&golden).unwrap()
+    );
+
+    let _ = std::fs::remove_file(output);
+    let _ = std::fs::remove_file(empty);
+    sent
+}
+
+/// Payload of the first UDP datagram in `frames`, after checking its lengths and checksum
+fn first_udp_payload(frames: &[Vec<u8>]) -> Vec<u8> {
+    let eth = EthernetPacket::new(&frames[0]).unwrap();
+    let ipv4 = Ipv4Packet::new(eth.payload()).unwrap();
+    let udp = UdpPacket::new(ipv4.payload()).unwrap();
+
+    assert_eq!(eth.payload().len(), ipv4.get_total_length() as usize);
+    assert_eq!(ipv4.payload().len(), udp.get_length() as usize);
+    assert_eq!(
+        udp.get_checksum(),
+        udp::ipv4_checksum(&udp, &ipv4.get_source(), &ipv4.get_destination())
+    );
+    udp.payload().to_vec()
+}
+
+#[test]
+fn longer_word_matches_golden() {
+    let sent = change_word("hello", "goodbye", "change_word-longer-out.pcap");
+    assert_eq!(first_udp_payload(&sent), b"goodbye world, goodbye");
+}
+
+#[test]
+fn shorter_word_matches_golden() {
+    let sent = change_word("hello", "hi", "change_word-shorter-out.pcap");
+    assert_eq!(first_udp_payload(&sent), b"hi world, hi");
+}
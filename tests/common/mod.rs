@@ -0,0 +1,51 @@
+use std::process::Command;
+
+/// veth pair deleted on drop, even if the test panics.
+pub struct Veth {
+    name: &'static str,
+}
+
+impl Veth {
+    pub fn new(name: &'static str, peer: &'static str) -> Veth {
+        Veth::with_queues(name, peer, 1)
+    }
+
+    /// veth pair with `queues` RX and TX queues on each end
+    pub fn with_queues(name: &'static str, peer: &'static str, queues: usize) -> Veth {
+        let queues = queues.to_string();
+        let queues = [
+            "numrxqueues",
+            queues.as_str(),
+            "numtxqueues",
+            queues.as_str(),
+        ];
+
+        ip(&["link", "del", name]);
+        let mut args = vec!["link", "add", name];
+        args.extend(queues);
+        args.extend(["type", "veth", "peer", "name", peer]);
+        args.extend(queues);
+        ip_ok(&args);
+        ip_ok(&["link", "set", name, "up"]);
+        ip_ok(&["link", "set", peer, "up"]);
+        Veth { name }
+    }
+}
+
+impl Drop for Veth {
+    fn drop(&mut self) {
+        ip(&["link", "del", self.name]);
+    }
+}
+
+fn ip(args: &[&str]) -> bool {
+    Command::new("ip")
+        .args(args)
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+fn ip_ok(args: &[&str]) {
+    assert!(ip(args), "ip {} failed", args.join(" "));
+}
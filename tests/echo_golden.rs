@@ -0,0 +1,108 @@
+//! The echo example's processing against golden pcap files, without root.
+//!
+//! `data/echo-in.pcap` holds ARP, ICMP, UDP echo and NDP requests to 10.0.0.4 and
+//! 2001:db8::1, and frames the example must drop. `data/echo-out.pcap` holds the
+//! expected replies. After an intended change of the example, regenerate it with:
+//! ```sh
+//! PV_UPDATE_GOLDEN=1 cargo test --test echo_golden
+//! ```
+
+#[path = "../examples/echo/process.rs"]
+mod process;
+
+use pnet::datalink::MacAddr;
+use pnet::packet::arp::{ArpOperations, ArpPacket};
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket};
+use pnet::packet::Packet;
+use pv::capture::PcapReader;
+use pv::offline::{OfflineConfig, OfflineNic};
+use std::path::{Path, PathBuf};
+
+const MAC: MacAddr = MacAddr(0x02, 0, 0, 0, 0, 0x04);
+
+fn data(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
+
+fn frames(path: &Path) -> Vec<Vec<u8>> {
+    let mut reader = PcapReader::open(path).unwrap();
+    let mut frames = Vec::new();
+    while let Some(frame) = reader.next_frame().unwrap() {
+        frames.push(frame.data);
+    }
+    frames
+}
+
+/// Runs the echo example over `echo-in.pcap` and returns the file of its replies
+fn echo(name: &str) -> PathBuf {
+    let umem = pv::Umem::offline(&pv::UmemConfig::default().chunk_count(64)).unwrap();
+    let output = std::env::temp_dir().join(format!("pv-{}-{}.pcap", std::process::id(), name));
+
+    let config = OfflineConfig::default()
+        .output(&output)
+        .mac(MAC)
+        .ip("10.0.0.4/24".parse().unwrap())
+        .ip("2001:db8::1/64".parse().unwrap());
+    let mut nic = OfflineNic::new("veth0", data("echo-in.pcap"), &umem, &config).unwrap();
+    while !nic.is_exhausted() {
+        process::do_echo(&mut nic);
+    }
+    nic.flush().unwrap();
+    drop(nic);
+
+    assert_eq!(umem.free_chunks(), umem.chunk_count());
+    output
+}
+
+#[test]
+fn arp_reply_targets_requester() {
+    let requests = frames(&data("echo-in.pcap"));
+    let output = echo("arp");
+    let replies = frames(&output);
+    let _ = std::fs::remove_file(output);
+
+    let arp = |frame: &[u8]| {
+        let eth = EthernetPacket::new(frame).unwrap();
+        (eth.get_ethertype() == EtherTypes::Arp)
+            .then(|| ArpPacket::owned(eth.payload().to_vec()).unwrap())
+    };
+    let request = requests.iter().find_map(|frame| arp(frame)).unwrap();
+    let reply = replies.iter().find_map(|frame| arp(frame)).unwrap();
+
+    assert_eq!(reply.get_operation(), ArpOperations::Reply);
+    assert_eq!(reply.get_sender_hw_addr(), MAC);
+    assert_eq!(
+        reply.get_sender_proto_addr(),
+        request.get_target_proto_addr()
+    );
+    assert_eq!(reply.get_target_hw_addr(), request.get_sender_hw_addr());
+    assert_eq!(
+        reply.get_target_proto_addr(),
+        request.get_sender_proto_addr()
+    );
+}
+
+#[test]
+fn echo_matches_golden() {
+    let output = echo("echo-out");
+
+    let golden = data("echo-out.pcap");
+    if std::env::var_os("PV_UPDATE_GOLDEN").is_some() {
+        std::fs::copy(&output, &golden).unwrap();
+    }
+
+    // Compare the frames first, for a readable failure.
+    let expected = frames(&golden);
+    let replies = frames(&output);
+    for (i, (reply, expected)) in replies.iter().zip(&expected).enumerate() {
+        assert_eq!(reply, expected, "reply {}", i);
+    }
+    assert_eq!(replies.len(), expected.len());
+    assert_eq!(
+        std::fs::read(&output).unwrap(),
+        std::fs::read(&golden).unwrap()
+    );
+    let _ = std::fs::remove_file(output);
+}
@@ -0,0 +1,118 @@
+//! The filter example's processing against golden pcap files, without root.
+//!
+//! `data/filter-in.pcap` holds TCP segments to and from port 80 with and without the
+//! word "pineapple", and other traffic the example must forward as is.
+//! `data/filter-out-back.pcap` holds the expected RSTs sent back to the input side and
+//! `data/filter-out-forward.pcap` the expected frames forwarded to the other side.
+//! After an intended change of the example, regenerate them with:
+//! ```sh
+//! PV_UPDATE_GOLDEN=1 cargo test --test filter_golden
+//! ```
+
+#[path = "../examples/filter/process.rs"]
+mod process;
+
+use pnet::packet::ethernet::EthernetPacket;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::tcp::{TcpFlags, TcpPacket};
+use pnet::packet::Packet;
+use pv::capture::{CaptureConfig, PcapReader, PcapWriter};
+use pv::offline::{OfflineConfig, OfflineNic};
+use std::path::{Path, PathBuf};
+
+fn data(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
+
+fn temp(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("pv-{}-{}", std::process::id(), name))
+}
+
+fn frames(path: &Path) -> Vec<Vec<u8>> {
+    let mut reader = PcapReader::open(path).unwrap();
+    let mut frames = Vec::new();
+    while let Some(frame) = reader.next_frame().unwrap() {
+        frames.push(frame.data);
+    }
+    frames
+}
+
+/// Compare `output` with the golden file `name`, frames first for a readable failure.
+fn assert_golden(output: &Path, name: &str) {
+    let golden = data(name);
+    if std::env::var_os("PV_UPDATE_GOLDEN").is_some() {
+        std::fs::copy(output, &golden).unwrap();
+    }
+
+    let expected = frames(&golden);
+    let sent = frames(output);
+    for (i, (frame, expected)) in sent.iter().zip(&expected).enumerate() {
+        assert_eq!(frame, expected, "{} frame {}", name, i);
+    }
+    assert_eq!(sent.len(), expected.len(), "{}", name);
+    assert_eq!(
+        std::fs::read(output).unwrap(),
+        std::fs::read(&golden).unwrap()
+    );
+}
+
+fn is_rst(frame: &[u8]) -> bool {
+    let eth = EthernetPacket::new(frame).unwrap();
+    let ipv4 = Ipv4Packet::new(eth.payload()).unwrap();
+    ipv4.get_next_level_protocol() == IpNextHeaderProtocols::Tcp
+        && TcpPacket::new(ipv4.payload()).unwrap().get_flags() & TcpFlags::RST != 0
+}
+
+#[test]
+fn filter_matches_golden() {
+    let umem = pv::Umem::offline(&pv::UmemConfig::default().chunk_count(64)).unwrap();
+    let back = temp("filter-out-back.pcap");
+    let forward = temp("filter-out-forward.pcap");
+
+    // The forwarding side only sends.
+    let empty = temp("filter-empty.pcap");
+    drop(PcapWriter::new(&empty, &CaptureConfig::default()).unwrap());
+
+    let mut nic1 = OfflineNic::new(
+        "veth1",
+        data("filter-in.pcap"),
+        &umem,
+        &OfflineConfig::default().output(&back),
+    )
+    .unwrap();
+    let mut nic2 = OfflineNic::new(
+        "veth2",
+        &empty,
+        &umem,
+        &OfflineConfig::default().output(&forward),
+    )
+    .unwrap();
+    while !nic1.is_exhausted() {
+        process::forward(&mut nic1, &mut nic2);
+    }
+    drop(nic1);
+    drop(nic2);
+
+    assert_golden(&back, "filter-out-back.pcap");
+    assert_golden(&forward, "filter-out-forward.pcap");
+
+    // Two of the five input frames carry the word on port 80.
+    let back = frames(&back);
+    let forward = frames(&forward);
+    assert_eq!(back.len(), 2);
+    assert!(back.iter().all(|frame| is_rst(frame)));
+    assert_eq!(forward.len(), 3 + 2);
+    assert_eq!(forward.iter().filter(|frame| is_rst(frame)).count(), 2);
+    assert_eq!(umem.free_chunks(), umem.chunk_count());
+
+    for path in [
+        temp("filter-out-back.pcap"),
+        temp("filter-out-forward.pcap"),
+        empty,
+    ] {
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,43 @@
+//! NicGroup over a veth pair with several RX queues.
+//!
+//! Creates the veth pairs `pvtest8` - `pvtest9` and `pvtest10` - `pvtest11`, so it
+//! needs root:
+//! ```sh
+//! sudo -E cargo test --test nic_group -- --ignored
+//! ```
+
+mod common;
+
+use common::Veth;
+
+const IF_NAME: &str = "pvtest8";
+const IF_PEER: &str = "pvtest9";
+const QUEUES: usize = 4;
+const IF_SINGLE: &str = "pvtest10";
+const IF_SINGLE_PEER: &str = "pvtest11";
+
+#[test]
+#[ignore]
+fn binds_every_rx_queue() {
+    let _veth = Veth::with_queues(IF_NAME, IF_PEER, QUEUES);
+
+    let umem = pv::Umem::new(&pv::UmemConfig::default().chunk_count(1024)).unwrap();
+    let group = pv::NicGroup::new(IF_NAME, &umem, &pv::NicConfig::default()).unwrap();
+
+    assert_eq!(group.len(), QUEUES);
+    for (queue_id, nic) in group.nics().iter().enumerate() {
+        assert_eq!(nic.queue_id(), queue_id as u32);
+    }
+}
+
+#[test]
+#[ignore]
+fn binds_single_queue() {
+    let _veth = Veth::new(IF_SINGLE, IF_SINGLE_PEER);
+
+    let umem = pv::Umem::new(&pv::UmemConfig::default()).unwrap();
+    let group = pv::NicGroup::new(IF_SINGLE, &umem, &pv::NicConfig::default()).unwrap();
+
+    assert_eq!(group.len(), 1);
+    assert_eq!(group.nics()[0].queue_id(), 0);
+}
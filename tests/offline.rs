@@ -0,0 +1,91 @@
+//! Packet processing against pcap files, without root.
+
+use pv::capture::{CaptureConfig, CaptureFormat, PcapReader, PcapWriter};
+use pv::offline::{OfflineConfig, OfflineNic};
+use std::path::PathBuf;
+use std::time::Duration;
+
+const FRAME_COUNT: usize = 100;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("pv-{}-{}", std::process::id(), name))
+}
+
+fn frame(seq: usize) -> Vec<u8> {
+    let mut frame = vec![0u8; 60 + seq];
+    frame[0..6].copy_from_slice(&[0x02, 0, 0, 0, 0, 1]); // dst
+    frame[6..12].copy_from_slice(&[0x02, 0, 0, 0, 0, 2]); // src
+    frame[12..14].copy_from_slice(&0x88B5u16.to_be_bytes());
+    frame[14] = seq as u8;
+    frame
+}
+
+/// Write `FRAME_COUNT` frames, one per millisecond.
+fn write_input(umem: &pv::Umem, format: CaptureFormat) -> PathBuf {
+    let path = temp_path(&format!("in-{:?}", format));
+    let mut writer = PcapWriter::new(&path, &CaptureConfig::default().format(format)).unwrap();
+    for seq in 0..FRAME_COUNT {
+        let mut packet = umem.alloc_packet().unwrap();
+        packet.replace_data(&frame(seq)).unwrap();
+        let time = Duration::from_millis(seq as u64);
+        assert!(writer.write_at(&packet, "in0", time).unwrap());
+    }
+
+    path
+}
+
+/// Swap the MAC addresses, like the echo example.
+fn echo(packet: &mut pv::Packet) {
+    let buffer = packet.get_buffer_mut();
+    let (dst, src) = buffer[0..12].split_at_mut(6);
+    dst.swap_with_slice(src);
+}
+
+fn run(format: CaptureFormat) {
+    let umem = pv::Umem::offline(&pv::UmemConfig::default().chunk_count(64)).unwrap();
+    let input = write_input(&umem, format);
+    let output = temp_path(&format!("out-{:?}", format));
+
+    let config = OfflineConfig::default()
+        .output(&output)
+        .output_format(format);
+    let mut nic = OfflineNic::new("veth1", &input, &umem, &config).unwrap();
+    while !nic.is_exhausted() {
+        let mut packets = nic.receive(16);
+        packets.iter_mut().for_each(echo);
+        nic.send(&mut packets);
+    }
+    nic.flush().unwrap();
+
+    let counters = nic.counters();
+    assert_eq!(counters.rx_packets(), FRAME_COUNT as u64);
+    assert_eq!(counters.tx_packets(), FRAME_COUNT as u64);
+    assert_eq!(umem.free_chunks(), umem.chunk_count());
+
+    let mut reader = PcapReader::open(&output).unwrap();
+    let mut count = 0;
+    while let Some(sent) = reader.next_frame().unwrap() {
+        let mut expected = frame(count);
+        let (dst, src) = expected[0..12].split_at_mut(6);
+        dst.swap_with_slice(src);
+        assert_eq!(sent.data, expected, "frame {}", count);
+        if format == CaptureFormat::Pcapng {
+            assert_eq!(sent.interface.as_deref(), Some("veth1"));
+        }
+        count += 1;
+    }
+    assert_eq!(count, FRAME_COUNT);
+
+    let _ = std::fs::remove_file(input);
+    let _ = std::fs::remove_file(output);
+}
+
+#[test]
+fn echo_pcap() {
+    run(CaptureFormat::Pcap);
+}
+
+#[test]
+fn echo_pcapng() {
+    run(CaptureFormat::Pcapng);
+}
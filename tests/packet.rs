@@ -0,0 +1,192 @@
+//! Headroom, tailroom and resizing of `pv::Packet`, on an offline UMEM.
+
+use pv::Error;
+
+const DATA_SIZE: usize = 100;
+
+fn umem() -> pv::Umem {
+    pv::Umem::offline(&pv::UmemConfig::default().chunk_count(64)).unwrap()
+}
+
+fn data() -> Vec<u8> {
+    (0..DATA_SIZE).map(|i| i as u8).collect()
+}
+
+fn packet(umem: &pv::Umem) -> pv::Packet {
+    let mut packet = umem.alloc_packet().unwrap();
+    packet.replace_data(&data()).unwrap();
+    packet
+}
+
+#[test]
+fn push_front_exhausts_headroom() {
+    let umem = umem();
+    let mut packet = packet(&umem);
+    let headroom = packet.headroom();
+    assert!(headroom > 0);
+
+    assert!(packet.push_front(0).unwrap().is_empty());
+    assert_eq!(packet.get_buffer(), data());
+
+    packet.push_front(headroom).unwrap().fill(0xff);
+    assert_eq!(packet.headroom(), 0);
+    assert_eq!(packet.get_buffer().len(), headroom + DATA_SIZE);
+    assert!(packet.get_buffer()[..headroom].iter().all(|&b| b == 0xff));
+    assert_eq!(packet.get_buffer()[headroom..], data());
+
+    assert!(matches!(
+        packet.push_front(1),
+        Err(Error::BufferTooSmall {
+            requested: 1,
+            available: 0
+        })
+    ));
+    assert_eq!(packet.get_buffer().len(), headroom + DATA_SIZE);
+}
+
+#[test]
+fn pull_front_empties_payload() {
+    let umem = umem();
+    let mut packet = packet(&umem);
+    let headroom = packet.headroom();
+
+    packet.pull_front(0).unwrap();
+    assert_eq!(packet.get_buffer(), data());
+
+    packet.pull_front(10).unwrap();
+    assert_eq!(packet.headroom(), headroom + 10);
+    assert_eq!(packet.get_buffer(), &data()[10..]);
+
+    assert!(matches!(
+        packet.pull_front(DATA_SIZE),
+        Err(Error::PayloadTooShort {
+            requested: DATA_SIZE,
+            available: 90
+        })
+    ));
+    assert_eq!(packet.get_buffer(), &data()[10..]);
+
+    packet.pull_front(DATA_SIZE - 10).unwrap();
+    assert!(packet.get_buffer().is_empty());
+    assert!(packet.pull_front(1).is_err());
+    packet.pull_front(0).unwrap();
+}
+
+#[test]
+fn push_back_exhausts_tailroom() {
+    let umem = umem();
+    let mut packet = packet(&umem);
+    let tailroom = packet.tailroom();
+    assert_eq!(packet.headroom() + DATA_SIZE + tailroom, packet.buffer_size);
+
+    assert!(packet.push_back(0).unwrap().is_empty());
+    assert_eq!(packet.get_buffer(), data());
+
+    packet.push_back(tailroom).unwrap().fill(0xff);
+    assert_eq!(packet.tailroom(), 0);
+    assert_eq!(packet.get_buffer()[..DATA_SIZE], data());
+    assert!(packet.get_buffer()[DATA_SIZE..].iter().all(|&b| b == 0xff));
+
+    assert!(matches!(
+        packet.push_back(1),
+        Err(Error::BufferTooSmall {
+            requested: 1,
+            available: 0
+        })
+    ));
+    assert_eq!(packet.get_buffer().len(), DATA_SIZE + tailroom);
+}
+
+#[test]
+fn trim_back_empties_payload() {
+    let umem = umem();
+    let mut packet = packet(&umem);
+    let tailroom = packet.tailroom();
+
+    packet.trim_back(0).unwrap();
+    assert_eq!(packet.get_buffer(), data());
+
+    assert!(matches!(
+        packet.trim_back(DATA_SIZE + 1),
+        Err(Error::PayloadTooShort {
+            requested: 101,
+            available: DATA_SIZE
+        })
+    ));
+    assert_eq!(packet.get_buffer(), data());
+
+    packet.trim_back(DATA_SIZE).unwrap();
+    assert!(packet.get_buffer().is_empty());
+    assert_eq!(packet.tailroom(), tailroom + DATA_SIZE);
+    assert!(packet.trim_back(1).is_err());
+}
+
+#[test]
+fn resize_within_tailroom() {
+    let umem = umem();
+    let mut packet = packet(&umem);
+    let headroom = packet.headroom();
+
+    packet.resize(DATA_SIZE / 2).unwrap();
+    assert_eq!(packet.headroom(), headroom);
+    assert_eq!(packet.get_buffer(), &data()[..DATA_SIZE / 2]);
+
+    packet.resize(0).unwrap();
+    assert_eq!(packet.headroom(), headroom);
+    assert!(packet.get_buffer().is_empty());
+
+    packet.resize(packet.buffer_size - headroom).unwrap();
+    assert_eq!(packet.headroom(), headroom);
+    assert_eq!(packet.tailroom(), 0);
+    assert_eq!(
+        packet.get_buffer()[..DATA_SIZE / 2],
+        data()[..DATA_SIZE / 2]
+    );
+}
+
+#[test]
+fn resize_beyond_tailroom_moves_payload() {
+    let umem = umem();
+    let mut packet = packet(&umem);
+    let headroom = packet.headroom();
+
+    // Only as much headroom as needed is given up.
+    packet.resize(packet.buffer_size - 10).unwrap();
+    assert!(headroom > 10);
+    assert_eq!(packet.headroom(), 10);
+    assert_eq!(packet.tailroom(), 0);
+    assert_eq!(packet.get_buffer()[..DATA_SIZE], data());
+
+    packet.resize(packet.buffer_size).unwrap();
+    assert_eq!(packet.headroom(), 0);
+    assert_eq!(packet.get_buffer().len(), packet.buffer_size);
+    assert_eq!(packet.get_buffer()[..DATA_SIZE], data());
+
+    assert!(matches!(
+        packet.resize(packet.buffer_size + 1),
+        Err(Error::BufferTooSmall { .. })
+    ));
+    assert_eq!(packet.get_buffer().len(), packet.buffer_size);
+}
+
+#[test]
+fn resize_moves_only_the_payload() {
+    let umem = umem();
+    let mut packet = packet(&umem);
+
+    // Payload ending at the end of the chunk, after some headroom.
+    packet.pull_front(DATA_SIZE / 2).unwrap();
+    packet.push_back(packet.tailroom()).unwrap().fill(0xff);
+    let len = packet.get_buffer().len();
+    let headroom = packet.headroom();
+
+    packet.resize(len + 1).unwrap();
+    assert_eq!(packet.headroom(), headroom - 1);
+    assert_eq!(
+        packet.get_buffer()[..DATA_SIZE / 2],
+        data()[DATA_SIZE / 2..]
+    );
+    assert!(packet.get_buffer()[DATA_SIZE / 2..len]
+        .iter()
+        .all(|&b| b == 0xff));
+}
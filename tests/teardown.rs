@@ -0,0 +1,124 @@
+//! UMEM and Nic teardown over a veth pair.
+//!
+//! Creates the veth pairs `pvtest2` - `pvtest3` and `pvtest6` - `pvtest7`, so it
+//! needs root:
+//! ```sh
+//! sudo -E cargo test --test teardown -- --ignored
+//! ```
+
+mod common;
+
+use common::Veth;
+use std::collections::HashSet;
+
+const IF_TX: &str = "pvtest2";
+const IF_RX: &str = "pvtest3";
+const IF_REOPEN_TX: &str = "pvtest6";
+const IF_REOPEN_RX: &str = "pvtest7";
+const WARMUP_CYCLES: usize = 20;
+const CYCLES: usize = 200;
+const REOPEN_CYCLES: usize = 1000;
+const BATCH_SIZE: usize = 16;
+const MAX_RSS_GROWTH: usize = 1 << 20;
+
+/// Resident set size of this process in bytes
+fn rss() -> usize {
+    let statm = std::fs::read_to_string("/proc/self/statm").unwrap();
+    let pages: usize = statm.split_whitespace().nth(1).unwrap().parse().unwrap();
+    pages * unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize
+}
+
+/// Allocate every free chunk, and check that the pool hands out none of them twice.
+fn assert_no_double_free(umem: &pv::Umem, cycle: usize) {
+    let free = umem.free_chunks();
+    assert!(
+        free <= umem.chunk_count(),
+        "cycle {}: {} free chunks out of {}",
+        cycle,
+        free,
+        umem.chunk_count()
+    );
+
+    let mut packets = Vec::with_capacity(free);
+    umem.alloc_packets_into(&mut packets, free);
+    let mut addrs = HashSet::with_capacity(free);
+    for packet in &packets {
+        assert!(
+            addrs.insert(packet.get_buffer().as_ptr()),
+            "cycle {}: chunk {:p} is free twice",
+            cycle,
+            packet.get_buffer().as_ptr()
+        );
+    }
+}
+
+/// Open both ends, pass some frames and close them again.
+fn cycle(tx_umem: &pv::Umem, rx_umem: &pv::Umem) {
+    let config = pv::NicConfig::default();
+    let mut tx = pv::Nic::new(IF_TX, tx_umem, &config).unwrap();
+    let mut rx = pv::Nic::new(IF_RX, rx_umem, &config).unwrap();
+
+    let mut packets = tx.alloc_packets(BATCH_SIZE).unwrap();
+    for packet in packets.iter_mut() {
+        packet.resize(64).unwrap();
+        packet.get_buffer_mut().fill(0xff);
+    }
+    tx.send(&mut packets);
+    drop(rx.receive(BATCH_SIZE));
+}
+
+#[test]
+#[ignore]
+fn no_leak_on_reopen() {
+    let _veth = Veth::new(IF_TX, IF_RX);
+
+    let umem_config = pv::UmemConfig::default().chunk_count(256);
+    let tx_umem = pv::Umem::new(&umem_config).unwrap();
+    let rx_umem = pv::Umem::new(&umem_config).unwrap();
+
+    for _ in 0..WARMUP_CYCLES {
+        cycle(&tx_umem, &rx_umem);
+    }
+
+    let rss_before = rss();
+    for i in 0..CYCLES {
+        cycle(&tx_umem, &rx_umem);
+        assert_no_double_free(&tx_umem, i);
+        assert_no_double_free(&rx_umem, i);
+        assert_eq!(tx_umem.free_chunks(), tx_umem.chunk_count(), "cycle {}", i);
+        assert_eq!(rx_umem.free_chunks(), rx_umem.chunk_count(), "cycle {}", i);
+    }
+    let rss_after = rss();
+
+    println!("RSS {} -> {}", rss_before, rss_after);
+    assert!(rss_after < rss_before + MAX_RSS_GROWTH);
+}
+
+#[test]
+#[ignore]
+fn close_returns_every_chunk() {
+    let _veth = Veth::new(IF_REOPEN_TX, IF_REOPEN_RX);
+
+    let umem_config = pv::UmemConfig::default().chunk_count(256);
+    let tx_umem = pv::Umem::new(&umem_config).unwrap();
+    let rx_umem = pv::Umem::new(&umem_config).unwrap();
+    let config = pv::NicConfig::default();
+    let mut tx = pv::Nic::new(IF_REOPEN_TX, &tx_umem, &config).unwrap();
+
+    for i in 0..REOPEN_CYCLES {
+        let rx = pv::Nic::new(IF_REOPEN_RX, &rx_umem, &config).unwrap();
+        assert!(rx_umem.free_chunks() < rx_umem.chunk_count());
+
+        // Close while frames are still arriving, and some wait in the RX ring.
+        let mut packets = tx.alloc_packets(BATCH_SIZE).unwrap();
+        for packet in packets.iter_mut() {
+            packet.resize(64).unwrap();
+            packet.get_buffer_mut().fill(0xff);
+        }
+        tx.send(&mut packets);
+        drop(rx);
+
+        assert_no_double_free(&rx_umem, i);
+        assert_eq!(rx_umem.free_chunks(), rx_umem.chunk_count(), "cycle {}", i);
+    }
+}
@@ -0,0 +1,155 @@
+//! TX buffer ownership under load over a veth pair.
+//!
+//! veth only binds in copy mode, where the kernel copies a frame before it is
+//! completed, so `no_payload_corruption_under_load` can only catch a chunk
+//! reused too early on zero-copy drivers. `send_keeps_chunks_until_completion`
+//! checks the ownership itself, and fails in any mode.
+//!
+//! Creates the veth pairs `pvtest0` - `pvtest1` and `pvtest4` - `pvtest5`, so it needs root:
+//! ```sh
+//! sudo -E cargo test --test veth_stress -- --ignored
+//! ```
+
+mod common;
+
+use common::Veth;
+use std::time::{Duration, Instant};
+
+const IF_TX: &str = "pvtest0";
+const IF_RX: &str = "pvtest1";
+const IF_OWNERSHIP: &str = "pvtest4";
+const IF_OWNERSHIP_PEER: &str = "pvtest5";
+const ETHERTYPE: u16 = 0x88B5; // IEEE local experimental
+const FRAME_SIZE: usize = 1024;
+const PACKET_COUNT: u64 = 200_000;
+const BATCH_SIZE: usize = 64;
+
+/// Payload byte at `offset` of frame `seq`
+fn pattern(seq: u64, offset: usize) -> u8 {
+    (seq as usize).wrapping_mul(31).wrapping_add(offset) as u8
+}
+
+fn fill(packet: &mut pv::Packet, seq: u64) {
+    packet.resize(FRAME_SIZE).unwrap();
+    let frame = packet.get_buffer_mut();
+    frame[0..12].fill(0xff);
+    frame[12..14].copy_from_slice(&ETHERTYPE.to_be_bytes());
+    frame[14..22].copy_from_slice(&seq.to_be_bytes());
+    for (offset, byte) in frame.iter_mut().enumerate().skip(22) {
+        *byte = pattern(seq, offset);
+    }
+}
+
+/// Returns `None` if the frame is not from this test, `Some(false)` if it is corrupted.
+fn verify(packet: &mut pv::Packet) -> Option<bool> {
+    let frame = packet.get_buffer_mut();
+    if frame.len() < 22 || frame[12..14] != ETHERTYPE.to_be_bytes() {
+        return None;
+    }
+
+    let seq = u64::from_be_bytes(frame[14..22].try_into().unwrap());
+    Some(
+        frame.len() == FRAME_SIZE
+            && frame
+                .iter()
+                .enumerate()
+                .skip(22)
+                .all(|(offset, byte)| *byte == pattern(seq, offset)),
+    )
+}
+
+#[test]
+#[ignore]
+fn no_payload_corruption_under_load() {
+    let _veth = Veth::new(IF_TX, IF_RX);
+
+    // Few chunks, so that every chunk is reused many times while frames are in flight.
+    let umem_config = pv::UmemConfig::default().chunk_count(256);
+    let tx_umem = pv::Umem::new(&umem_config).unwrap();
+    let rx_umem = pv::Umem::new(&umem_config).unwrap();
+    let config = pv::NicConfig::default();
+    let mut tx = pv::Nic::new(IF_TX, &tx_umem, &config).unwrap();
+    let mut rx = pv::Nic::new(IF_RX, &rx_umem, &config).unwrap();
+
+    let mut packets = Vec::with_capacity(BATCH_SIZE);
+    let mut seq = 0;
+    let mut received = 0;
+    let mut corrupted = 0;
+    let deadline = Instant::now() + Duration::from_secs(60);
+
+    while (seq < PACKET_COUNT || !packets.is_empty()) && Instant::now() < deadline {
+        let wanted = (BATCH_SIZE - packets.len()).min((PACKET_COUNT - seq) as usize);
+        let count = tx.alloc_packets_into(&mut packets, wanted);
+        for packet in packets.iter_mut().rev().take(count) {
+            fill(packet, seq);
+            seq += 1;
+        }
+        tx.send(&mut packets);
+
+        for mut packet in rx.receive(BATCH_SIZE) {
+            match verify(&mut packet) {
+                Some(true) => received += 1,
+                Some(false) => corrupted += 1,
+                None => {}
+            }
+        }
+    }
+
+    // Drain the frames still on the way.
+    let drain_deadline = Instant::now() + Duration::from_secs(1);
+    while Instant::now() < drain_deadline {
+        for mut packet in rx.receive_timeout(BATCH_SIZE, Duration::from_millis(10)) {
+            match verify(&mut packet) {
+                Some(true) => received += 1,
+                Some(false) => corrupted += 1,
+                None => {}
+            }
+        }
+        tx.send(&mut Vec::new());
+    }
+
+    println!(
+        "sent {}, received {}, corrupted {}, in flight {}",
+        seq,
+        received,
+        corrupted,
+        tx.tx_in_flight()
+    );
+    assert_eq!(seq, PACKET_COUNT, "TX stalled: chunks are not recycled");
+    assert!(received > 0);
+    assert_eq!(corrupted, 0);
+}
+
+#[test]
+#[ignore]
+fn send_keeps_chunks_until_completion() {
+    let _veth = Veth::new(IF_OWNERSHIP, IF_OWNERSHIP_PEER);
+
+    let umem = pv::Umem::new(&pv::UmemConfig::default()).unwrap();
+    let mut nic = pv::Nic::new(IF_OWNERSHIP, &umem, &pv::NicConfig::default()).unwrap();
+
+    // The fill ring is filled once in Nic::new() and only refilled by receive().
+    let free_before = umem.free_chunks();
+    let mut packets = nic.alloc_packets(BATCH_SIZE).unwrap();
+    for (seq, packet) in packets.iter_mut().enumerate() {
+        fill(packet, seq as u64);
+    }
+
+    let sent = nic.send(&mut packets);
+    assert!(sent > 0);
+    drop(packets);
+
+    // send() reaps the completion ring before putting the new packets on the TX ring,
+    // so none of their chunks can be back yet.
+    assert_eq!(nic.tx_in_flight(), sent);
+    assert_eq!(umem.free_chunks(), free_before - sent);
+
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while nic.tx_in_flight() > 0 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(1));
+        nic.send(&mut Vec::new());
+    }
+
+    assert_eq!(nic.tx_in_flight(), 0, "TX chunks never completed");
+    assert_eq!(umem.free_chunks(), free_before);
+}